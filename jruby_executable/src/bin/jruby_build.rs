@@ -2,13 +2,20 @@ use bullet_stream::{global::print, style};
 use clap::Parser;
 use fs_err::{self as fs, PathExt};
 use indoc::formatdoc;
-use jruby_executable::{JRubyVersion, jruby_build_properties};
+use jruby_executable::release_check::artifact_file_name;
+use jruby_executable::{BuildProperties, JRubyVersion, jruby_build_properties};
 use libherokubuildpack::inventory::artifact::Arch;
 use reqwest::Url;
 use shared::{
-    BaseImage, BuildStatus, TarDownloadPath, append_filename_with, download_tar, s3_url_exists,
-    sha256_from_path, tar_dir_to_file, untar_to_dir, write_job_metadata,
+    BaseImage, BuildStatus, Compression, TarDownloadPath, TarMode, append_filename_with, audit,
+    download_tar, http,
+    job_result::{BuildOutput, JobResult, OutputFile},
+    provenance::{self, BuildInputs},
+    s3_url_exists, sha256_from_path,
+    summary::{BuildSummary, append_markdown},
+    tar_dir_to_file, untar_to_dir, update_shebangs_in_dir, write_job_metadata,
 };
+use std::collections::BTreeMap;
 use std::convert::From;
 use std::error::Error;
 use std::path::PathBuf;
@@ -41,9 +48,21 @@ struct Args {
 
     #[arg(long = "job-metadata")]
     job_metadata: Option<PathBuf>,
+
+    /// Compression for the output tarball, anything other than gzip is not yet downloaded by the buildpack
+    #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+    compression: Compression,
+
+    /// Write a JSON document describing the build's outputs, checksums and tools to this file
+    #[arg(long = "job-result")]
+    job_result: Option<PathBuf>,
+
+    /// Append a Markdown table of the build's outcome to this file, e.g. `$GITHUB_STEP_SUMMARY`
+    #[arg(long = "summary-markdown")]
+    summary_markdown: Option<PathBuf>,
 }
 
-async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
+async fn jruby_build(args: &Args) -> Result<BuildOutput, Box<dyn Error>> {
     let Args {
         version,
         base_image,
@@ -51,6 +70,9 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
         artifact_dir,
         cache_dir,
         job_metadata: _,
+        compression,
+        job_result: _,
+        summary_markdown: _,
     } = args;
 
     let start = Instant::now();
@@ -61,10 +83,9 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
     fs::create_dir_all(volume_cache_dir)?;
     fs::create_dir_all(volume_output_dir)?;
 
-    let ruby_stdlib_version = jruby_build_properties(version)
-        .await?
-        .ruby_stdlib_version()?;
-    let tgz_name = format!("ruby-{ruby_stdlib_version}-jruby-{version}.tgz");
+    let build_properties = jruby_build_properties(version).await?;
+    let ruby_stdlib_version = build_properties.ruby_stdlib_version()?;
+    let tgz_name = artifact_file_name(version, &ruby_stdlib_version, *compression);
     let expected_output = volume_output_dir
         .join(base_image.to_string())
         .join(&tgz_name);
@@ -85,7 +106,12 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
                     "Output already exists locally: {}, skipping",
                     expected_output.display()
                 ));
-                return Ok(BuildStatus::Skipped);
+                return Ok(BuildOutput {
+                    status: BuildStatus::Skipped,
+                    artifact_name: tgz_name,
+                    outputs: Vec::new(),
+                    tools: BTreeMap::new(),
+                });
             }
 
             let s3_path = expected_output.strip_prefix(volume_output_dir)?;
@@ -98,9 +124,14 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
             };
 
             print::bullet(format!("Checking if already uploaded: {url}"));
-            if s3_url_exists(url.clone()).await? {
+            if s3_url_exists(http::client()?, url.clone()).await? {
                 print::bullet(format!("Already exists: {url}, skipping"));
-                return Ok(BuildStatus::Skipped);
+                return Ok(BuildOutput {
+                    status: BuildStatus::Skipped,
+                    artifact_name: tgz_name,
+                    outputs: Vec::new(),
+                    tools: BTreeMap::new(),
+                });
             }
         }
         OnConflict::Overwrite => {}
@@ -111,6 +142,9 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
 
     let download_path =
         TarDownloadPath(volume_cache_dir.join(format!("jruby-dist-{version}-bin.tar.gz")));
+    let url = format!(
+        "https://repo1.maven.org/maven2/org/jruby/jruby-dist/{version}/jruby-dist-{version}-bin.tar.gz"
+    );
 
     if download_path.as_ref().fs_err_try_exists()? {
         print::bullet(format!(
@@ -118,22 +152,33 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
             download_path.as_ref().display()
         ));
     } else {
-        let url = format!(
-            "https://repo1.maven.org/maven2/org/jruby/jruby-dist/{version}/jruby-dist-{version}-bin.tar.gz"
-        );
         print::bullet("Download JRuby");
         print::sub_bullet(format!("To {}", download_path.as_ref().to_string_lossy()));
         print::sub_bullet(format!("From {}", style::url(&url)));
 
         let timer = print::sub_start_timer("Downloading");
-        download_tar(&url, &download_path).await?;
+        download_tar(http::client()?, &url, &download_path).await?;
         timer.done();
     }
 
     untar_to_dir(&download_path, &extracted_path)?;
+    let inputs = BuildInputs {
+        version: version.to_string(),
+        base_image: base_image.to_string(),
+        arch: None,
+        source_url: url,
+        source_sha256: sha256_from_path(download_path.as_ref())?,
+        dockerfile_sha256: None,
+        configure_options: Vec::new(),
+        builder_commit: provenance::builder_commit(),
+    };
 
     let jruby_dir = extracted_path.join(format!("jruby-{version}"));
 
+    print::bullet("Checking Ruby stdlib version");
+    build_properties.cross_check(&BuildProperties::from_dist(&jruby_dir)?)?;
+    print::sub_bullet(format!("Archive matches Ruby stdlib {ruby_stdlib_version}"));
+
     print::bullet("Removing unnecessary files");
     for pattern in &["*.bat", "*.dll", "*.exe"] {
         for path in glob::glob(&jruby_dir.join("bin").join(pattern).to_string_lossy())?
@@ -159,7 +204,16 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
         fs::os::unix::fs::symlink("jruby", ruby_bin)?;
     }
 
-    print::bullet("Creating tgz archives");
+    print::bullet("Rewrite Ruby shebangs");
+    let updated = update_shebangs_in_dir(&jruby_dir)?;
+    for path in &updated {
+        print::sub_bullet(format!("Rewrote {}", path.display()));
+    }
+    if updated.is_empty() {
+        print::sub_bullet("None found");
+    }
+
+    print::bullet(format!("Creating {compression} archives"));
     let tar_dir = volume_output_dir.join(base_image.to_string());
 
     fs::create_dir_all(&tar_dir)?;
@@ -167,16 +221,33 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
     let tar_file = fs::File::create(tar_dir.join(&tgz_name))?;
 
     let timer = print::sub_start_timer(format!("Write {}", tar_file.path().display()));
-    tar_dir_to_file(&jruby_dir, &tar_file)?;
+    tar_dir_to_file(
+        &jruby_dir,
+        &tar_file,
+        TarMode::reproducible_from_env()?,
+        *compression,
+    )?;
     timer.done();
 
     let tar_path = tar_file.path();
+    print::sub_bullet(format!("Audit {}", tar_path.display()));
+    for warning in audit::audit_tarball(tar_path)?.into_result()? {
+        print::sub_bullet(format!("Warning: {warning}"));
+    }
     let sha = sha256_from_path(tar_path)?;
     let sha_seven = sha.chars().take(7).collect::<String>();
-    let sha_seven_path = append_filename_with(tar_path, &format!("-{sha_seven}"), ".tgz")?;
+    let sha_seven_path =
+        append_filename_with(tar_path, &format!("-{sha_seven}"), compression.extension())?;
 
     print::sub_bullet(format!("Write {}", sha_seven_path.display(),));
     fs::copy(tar_file.path(), &sha_seven_path)?;
+    let provenance_path = inputs.write_for(tar_path, &sha, &[&sha_seven_path])?;
+    let mut outputs = vec![OutputFile {
+        path: tar_path.to_path_buf(),
+        sha256: sha.clone(),
+        sha_seven_path: Some(sha_seven_path),
+        provenance_path: Some(provenance_path),
+    }];
 
     // Can be removed once manifest file support is fully rolled out
     // because jruby is architecture independent. However the
@@ -190,19 +261,60 @@ async fn jruby_build(args: &Args) -> Result<BuildStatus, Box<dyn Error>> {
         let path = dir.join(&tgz_name);
         print::sub_bullet(format!("Write {}", path.display()));
         fs::copy(tar_file.path(), &path)?;
+        let provenance_path = inputs.write_for(&path, &sha, &[])?;
+        outputs.push(OutputFile {
+            path,
+            sha256: sha.clone(),
+            sha_seven_path: None,
+            provenance_path: Some(provenance_path),
+        });
     }
 
     print::all_done(&Some(start));
-    Ok(BuildStatus::Success)
+    Ok(BuildOutput {
+        status: BuildStatus::Success,
+        artifact_name: tgz_name,
+        outputs,
+        tools: BTreeMap::from([(
+            "jruby_build".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        )]),
+    })
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
     let metadata = args.job_metadata.as_deref();
-    match jruby_build(&args).await {
-        Ok(status) => {
-            if let Err(e) = write_job_metadata(metadata, "status", status.as_str()) {
+    let start = Instant::now();
+    let result = jruby_build(&args).await;
+    let job_result = JobResult::new(
+        &args.version,
+        &args.base_image,
+        None::<Arch>,
+        &result,
+        start.elapsed(),
+    );
+    if let Err(e) = job_result.write(args.job_result.as_deref()) {
+        print::error(format!("Failed to write job result: {e}"));
+    }
+    let summary = BuildSummary {
+        engine: "JRuby",
+        version: args.version.to_string(),
+        base_image: args.base_image.clone(),
+        arch: None,
+        status: result.as_ref().ok().map(|output| output.status),
+        artifact: result
+            .as_ref()
+            .ok()
+            .map(|output| output.artifact_name.clone()),
+    };
+    if let Err(e) = append_markdown(args.summary_markdown.as_deref(), &summary.to_markdown()) {
+        print::error(format!("Failed to write summary: {e}"));
+    }
+    match result {
+        Ok(output) => {
+            if let Err(e) = write_job_metadata(metadata, "status", output.status.as_str()) {
                 print::error(format!("Failed to write job metadata: {e}"));
             }
         }
@@ -1,15 +1,19 @@
 use bullet_stream::global::print;
 use clap::Parser;
 use indoc::formatdoc;
+use jruby_executable::engine::{ENGINE_COMMANDS, RubyEngine};
+use jruby_executable::release_check::artifact_file_name;
 use jruby_executable::{JRubyVersion, jruby_build_properties};
 use libherokubuildpack::inventory::artifact::Arch;
-use shared::{BaseImage, source_dir};
+use shared::check_result::{CheckResult, FACTS_COMMANDS, ImageResult, OutputFormat, RuntimeFacts};
+use shared::{BaseImage, Compression, source_dir};
 use std::error::Error;
 use std::io::Write;
 use std::time::Instant;
 use std::{path::PathBuf, process::Command};
 
 static INNER_OUTPUT: &str = "/tmp/output";
+static INNER_JRUBY: &str = "/tmp/unzipped";
 
 #[derive(Parser, Debug)]
 struct RubyArgs {
@@ -24,6 +28,14 @@ struct RubyArgs {
 
     #[arg(long = "artifact-dir")]
     artifact_dir: PathBuf,
+
+    /// Compression the tarball was built with
+    #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+    compression: Compression,
+
+    /// Print the results as Markdown for the GitHub step summary, or as JSON
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown)]
+    format: OutputFormat,
 }
 
 async fn jruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
@@ -32,6 +44,8 @@ async fn jruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
         version,
         base_image,
         artifact_dir,
+        compression,
+        format,
     } = args;
 
     let jruby_stdlib_version = jruby_build_properties(version)
@@ -71,10 +85,16 @@ async fn jruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
     docker_build.arg(source_dir().to_str().expect("Path to str"));
     print::sub_stream_cmd(docker_build)?;
 
+    let mut check = CheckResult::new("jruby", version, base_image, arch);
+    let mut failures = Vec::new();
     let output = {
         let inner_jruby_path = PathBuf::from(INNER_OUTPUT)
             .join(base_image.to_string())
-            .join(format!("ruby-{jruby_stdlib_version}-jruby-{version}.tgz"));
+            .join(artifact_file_name(
+                version,
+                &jruby_stdlib_version,
+                *compression,
+            ));
 
         let mut cmd = Command::new("docker");
         cmd.arg("run");
@@ -87,35 +107,84 @@ async fn jruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
                 outside_output = outside_output.display()
             ),
         ]);
-        cmd.arg(image_name);
+        cmd.arg(&image_name);
         cmd.args(["bash", "-c"]);
         cmd.arg(
             [
-                "mkdir /tmp/unzipped",
-                &format!("tar xzf {} -C /tmp/unzipped", inner_jruby_path.display()),
-                "export PATH=\"tmp/unzipped/bin:$PATH\"",
-                "echo -n '- Rubygems version: '",
-                "gem -v",
-                "echo -n '- Ruby version: '",
-                "ruby -v",
+                format!("mkdir {INNER_JRUBY}"),
+                // GNU tar detects the compression when extracting
+                format!("tar xf {} -C {INNER_JRUBY}", inner_jruby_path.display()),
+                format!("export PATH=\"{INNER_JRUBY}/bin:$PATH\""),
             ]
+            .iter()
+            .map(String::as_str)
+            .chain(ENGINE_COMMANDS)
+            .chain(FACTS_COMMANDS)
+            .collect::<Vec<_>>()
             .join(" && "),
         );
 
         print::bullet("Versions");
 
-        print::sub_stream_cmd(cmd)?
+        match print::sub_stream_cmd(cmd) {
+            Ok(output) => {
+                let stdout = output.stdout_lossy();
+                let mismatches = RubyEngine::parse(&stdout).mismatches(
+                    version,
+                    &jruby_stdlib_version,
+                    INNER_JRUBY,
+                );
+                if !mismatches.is_empty() {
+                    failures.push(format!(
+                        "The `ruby` in {image_name} is not JRuby {version} (stdlib {jruby_stdlib_version}):\n{}",
+                        mismatches
+                            .iter()
+                            .map(|mismatch| format!("  - {mismatch}"))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    ));
+                }
+                Some(stdout)
+            }
+            Err(error) => {
+                failures.push(format!("JRuby failed to run in {image_name}: {error}"));
+                None
+            }
+        }
     };
+    check.images.push(ImageResult {
+        image: image_name,
+        facts: output.as_deref().map(RuntimeFacts::parse),
+        tests: Vec::new(),
+    });
 
     print::all_done(&Some(start));
 
     print::plain("");
 
-    // Print results to STDOUT for github summary
-    println!("## JRuby {version} stdlib {jruby_stdlib_version} linux/{arch} for {base_image}");
-    println!();
-    println!("{}", output.stdout_lossy());
-    Ok(())
+    // Print results to STDOUT for the github summary, or for other tooling with `--format json`
+    match format {
+        OutputFormat::Markdown => {
+            println!(
+                "## JRuby {version} stdlib {jruby_stdlib_version} linux/{arch} for {base_image}"
+            );
+            println!();
+            println!(
+                "{}",
+                output.as_deref().unwrap_or("❌ Failed, see the job log\n")
+            );
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            check.finish(failures.clone(), start.elapsed()).to_json()
+        ),
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n\n").into())
+    }
 }
 
 #[tokio::main]
@@ -0,0 +1,133 @@
+//! Checking that `jruby_check` runs the JRuby under test
+//!
+//! The run image can ship its own `ruby`, so a `PATH` mistake would quietly check the wrong
+//! interpreter. [`ENGINE_COMMANDS`] report what the `ruby` on the `PATH` actually is, and
+//! [`RubyEngine::mismatches`] compares that with the requested JRuby and its stdlib version.
+
+use crate::JRubyVersion;
+
+/// Commands run with the extracted JRuby first on the `PATH`, joined with `&&`
+///
+/// They print `- <label>: <value>` lines, read back by [`RubyEngine::parse`]. Ruby is only
+/// started once since every JVM boot costs seconds.
+pub const ENGINE_COMMANDS: [&str; 2] = [
+    r#"ruby -e 'puts "- Ruby engine: #{RUBY_ENGINE}", "- JRuby version: #{defined?(JRUBY_VERSION) ? JRUBY_VERSION : "none"}", "- Ruby stdlib version: #{RUBY_VERSION}"'"#,
+    r#"echo "- Ruby executable: $(command -v ruby)""#,
+];
+
+/// What the `ruby` on the `PATH` reported, from the output of [`ENGINE_COMMANDS`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RubyEngine {
+    pub engine: Option<String>,
+    pub jruby_version: Option<String>,
+    pub ruby_version: Option<String>,
+    pub executable: Option<String>,
+}
+
+impl RubyEngine {
+    pub fn parse(output: &str) -> Self {
+        let mut engine = Self::default();
+        for line in output.lines() {
+            let Some((label, value)) = line
+                .strip_prefix("- ")
+                .and_then(|line| line.split_once(": "))
+            else {
+                continue;
+            };
+            let value = Some(value.trim().to_string());
+            match label {
+                "Ruby engine" => engine.engine = value,
+                "JRuby version" => engine.jruby_version = value,
+                "Ruby stdlib version" => engine.ruby_version = value,
+                "Ruby executable" => engine.executable = value,
+                _ => {}
+            }
+        }
+        engine
+    }
+
+    /// Differences from the JRuby `version` extracted to `jruby_dir`, which implements Ruby
+    /// `stdlib_version`. Empty when it's the JRuby under test.
+    pub fn mismatches(
+        &self,
+        version: &JRubyVersion,
+        stdlib_version: &str,
+        jruby_dir: &str,
+    ) -> Vec<String> {
+        let expected = [
+            ("RUBY_ENGINE", &self.engine, "jruby".to_string()),
+            ("JRUBY_VERSION", &self.jruby_version, version.to_string()),
+            (
+                "RUBY_VERSION",
+                &self.ruby_version,
+                stdlib_version.to_string(),
+            ),
+        ];
+        let mut mismatches = expected
+            .into_iter()
+            .filter(|(_, actual, expected)| actual.as_ref() != Some(expected))
+            .map(|(name, actual, expected)| {
+                format!(
+                    "{name} is {} but expected {expected}",
+                    actual.as_deref().unwrap_or("missing")
+                )
+            })
+            .collect::<Vec<_>>();
+        if !self
+            .executable
+            .as_deref()
+            .is_some_and(|executable| executable.starts_with(&format!("{jruby_dir}/")))
+        {
+            mismatches.push(format!(
+                "`ruby` is {} but expected it in {jruby_dir}/bin",
+                self.executable.as_deref().unwrap_or("missing")
+            ));
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracted_jruby_matches() {
+        let engine = RubyEngine::parse(indoc::indoc! {"
+            - Rubygems version: 3.3.26
+            - Ruby engine: jruby
+            - JRuby version: 9.4.8.0
+            - Ruby stdlib version: 3.1.4
+            - Ruby executable: /tmp/unzipped/bin/ruby
+        "});
+        let version = JRubyVersion::parse("9.4.8.0").unwrap();
+        assert_eq!(
+            engine.mismatches(&version, "3.1.4", "/tmp/unzipped"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn system_ruby_does_not_match() {
+        let engine = RubyEngine::parse(indoc::indoc! {"
+            - Ruby engine: ruby
+            - JRuby version: none
+            - Ruby stdlib version: 3.2.3
+            - Ruby executable: /usr/bin/ruby
+        "});
+        let version = JRubyVersion::parse("9.4.8.0").unwrap();
+        assert_eq!(
+            engine.mismatches(&version, "3.1.4", "/tmp/unzipped"),
+            vec![
+                "RUBY_ENGINE is ruby but expected jruby",
+                "JRUBY_VERSION is none but expected 9.4.8.0",
+                "RUBY_VERSION is 3.2.3 but expected 3.1.4",
+                "`ruby` is /usr/bin/ruby but expected it in /tmp/unzipped/bin",
+            ]
+        );
+        assert_eq!(
+            RubyEngine::default().mismatches(&version, "3.1.4", "/tmp/unzipped")[0],
+            "RUBY_ENGINE is missing but expected jruby"
+        );
+    }
+}
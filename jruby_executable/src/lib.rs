@@ -1,6 +1,14 @@
 // See `bin/*.rs` for scripts
 
+use shared::http::RequestClass;
+use shared::retry::{Classify, ErrorClass};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub mod engine;
 pub mod jruby_version;
+pub mod maven;
+pub mod release_check;
 pub use jruby_version::JRubyVersion;
 
 /// Short: This struct parses a file based on the input jruby version to determine
@@ -65,14 +73,99 @@ pub enum Error {
     #[error("Cannot find `ruby.version=` from {url}.\n Body:\n{body}")]
     CannotParseJrubyStdlibVersion { url: String, body: String },
 
+    #[error("Cannot find `<versions>` in Maven metadata from {url}.\n Body:\n{body}")]
+    CannotParseMavenMetadata { url: String, body: String },
+
     #[error("Failed to fetch {0}")]
     FailedRequest(#[from] reqwest::Error),
 
+    #[error(transparent)]
+    HttpClient(#[from] shared::http::HttpClientError),
+
+    #[error(transparent)]
+    HttpStatus(#[from] shared::http::StatusError),
+
     #[error("Failed to parse Java properties {0}")]
     InvalidProperties(#[from] java_properties::PropertiesError),
+
+    #[error("Cannot read {path} from {jar} due to error {source}")]
+    CannotReadJar {
+        jar: PathBuf,
+        path: String,
+        #[source]
+        source: zip::result::ZipError,
+    },
+
+    #[error(
+        "JRuby stdlib version mismatch. {build_properties_url} says {build_properties} but {dist_url} says {dist}"
+    )]
+    StdlibVersionMismatch {
+        build_properties_url: String,
+        build_properties: String,
+        dist_url: String,
+        dist: String,
+    },
 }
 
+impl Classify for Error {
+    fn classify(&self) -> ErrorClass {
+        match self {
+            Error::FailedRequest(error) => error.classify(),
+            Error::HttpClient(error) => error.classify(),
+            Error::HttpStatus(error) => error.classify(),
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// Location of the filtered properties file inside of `lib/jruby.jar`
+static JAR_PROPERTIES_PATH: &str = "org/jruby/jruby.properties";
+
 impl BuildProperties {
+    /// Reads the Ruby compatibility version shipped inside an extracted `jruby-dist` archive.
+    ///
+    /// The `lib/jruby.jar` contains `org/jruby/jruby.properties`, which is rendered from the
+    /// same `version.ruby` value as `default.build.properties` at release time, so the result
+    /// can be queried with [`BuildProperties::ruby_stdlib_version`] the same way.
+    pub fn from_dist(jruby_dir: &Path) -> Result<BuildProperties, Error> {
+        let jar = jruby_dir.join("lib").join("jruby.jar");
+        let cannot_read = |source| Error::CannotReadJar {
+            jar: jar.clone(),
+            path: JAR_PROPERTIES_PATH.to_string(),
+            source,
+        };
+
+        let file = fs_err::File::open(&jar).map_err(|error| cannot_read(error.into()))?;
+        let mut archive = zip::ZipArchive::new(file).map_err(cannot_read)?;
+        let mut entry = archive.by_name(JAR_PROPERTIES_PATH).map_err(cannot_read)?;
+
+        let mut body = Vec::new();
+        entry
+            .read_to_end(&mut body)
+            .map_err(|error| cannot_read(error.into()))?;
+
+        Ok(BuildProperties {
+            body,
+            url: format!("jar:file:{}!/{JAR_PROPERTIES_PATH}", jar.display()),
+        })
+    }
+
+    /// Returns the stdlib version when both sources agree, errors when they do not
+    pub fn cross_check(&self, other: &BuildProperties) -> Result<String, Error> {
+        let ours = self.ruby_stdlib_version()?;
+        let theirs = other.ruby_stdlib_version()?;
+        if ours == theirs {
+            Ok(ours)
+        } else {
+            Err(Error::StdlibVersionMismatch {
+                build_properties_url: self.url.clone(),
+                build_properties: ours,
+                dist_url: other.url.clone(),
+                dist: theirs,
+            })
+        }
+    }
+
     pub fn ruby_stdlib_version(&self) -> Result<String, Error> {
         java_properties::read(&self.body[..])
             .map_err(Error::InvalidProperties)
@@ -103,19 +196,14 @@ async fn jruby_build_properties_inner(
         "https://raw.githubusercontent.com/jruby/jruby/{jruby_version}/default.build.properties",
     );
 
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .map_err(Error::FailedRequest)?;
-    let response = client
+    let response = shared::http::client()?
         .get(&url)
+        .timeout(RequestClass::Api.timeout())
         .send()
         .await
         .map_err(Error::FailedRequest)?;
 
-    let body = response
-        .error_for_status()
-        .map_err(Error::FailedRequest)?
+    let body = shared::http::error_for_status(response)?
         .text()
         .await
         .map_err(Error::FailedRequest)?;
@@ -164,4 +252,63 @@ mod test {
 
         assert_eq!(properties.ruby_stdlib_version().unwrap(), "3.1.4");
     }
+
+    fn write_dist_jar(jruby_dir: &Path, properties: &str) {
+        let lib = jruby_dir.join("lib");
+        fs_err::create_dir_all(&lib).unwrap();
+        let mut jar = zip::ZipWriter::new(fs_err::File::create(lib.join("jruby.jar")).unwrap());
+        jar.start_file(
+            JAR_PROPERTIES_PATH,
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut jar, properties.as_bytes()).unwrap();
+        jar.finish().unwrap();
+    }
+
+    #[test]
+    fn test_jruby_stdlib_version_from_dist() {
+        let dir = tempfile::tempdir().unwrap();
+        write_dist_jar(
+            dir.path(),
+            &formatdoc! {"
+                version.ruby=3.1.4
+                version.jruby=9.4.7.0
+            "},
+        );
+
+        let properties = BuildProperties::from_dist(dir.path()).unwrap();
+        assert_eq!(properties.ruby_stdlib_version().unwrap(), "3.1.4");
+    }
+
+    #[test]
+    fn test_jruby_stdlib_version_from_dist_missing_jar() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            BuildProperties::from_dist(dir.path()),
+            Err(Error::CannotReadJar { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cross_check() {
+        let dir = tempfile::tempdir().unwrap();
+        write_dist_jar(dir.path(), "version.ruby=3.1.4\n");
+        let dist = BuildProperties::from_dist(dir.path()).unwrap();
+
+        let agrees = BuildProperties {
+            body: b"version.ruby=3.1.4\n".to_vec(),
+            url: "https://example.com".to_string(),
+        };
+        assert_eq!(agrees.cross_check(&dist).unwrap(), "3.1.4");
+
+        let disagrees = BuildProperties {
+            body: b"version.ruby=3.1.5\n".to_vec(),
+            url: "https://example.com".to_string(),
+        };
+        assert!(matches!(
+            disagrees.cross_check(&dist),
+            Err(Error::StdlibVersionMismatch { .. })
+        ));
+    }
 }
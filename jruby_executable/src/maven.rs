@@ -0,0 +1,144 @@
+//! JRuby releases as published to Maven Central
+//!
+//! `jruby_build` downloads `org.jruby:jruby-dist` from Maven Central, which can lag behind the
+//! GitHub release by hours. The artifact's `maven-metadata.xml` lists every published version:
+//!
+//! ```xml
+//! <metadata>
+//!   <groupId>org.jruby</groupId>
+//!   <artifactId>jruby-dist</artifactId>
+//!   <versioning>
+//!     <latest>10.0.2.0</latest>
+//!     <release>10.0.2.0</release>
+//!     <versions>
+//!       <version>9.4.7.0</version>
+//!       <version>10.0.2.0</version>
+//!     </versions>
+//!   </versioning>
+//! </metadata>
+//! ```
+
+use crate::{Error, JRubyVersion};
+use shared::http::RequestClass;
+use winnow::Parser;
+use winnow::ascii::multispace0;
+use winnow::combinator::{delimited, preceded, repeat, terminated};
+use winnow::token::take_until;
+
+pub static JRUBY_DIST_METADATA_URL: &str =
+    "https://repo1.maven.org/maven2/org/jruby/jruby-dist/maven-metadata.xml";
+
+/// The `<version>` entries of a `maven-metadata.xml` document, in listed order
+pub fn parse_metadata_versions(xml: &str) -> Option<Vec<String>> {
+    versions.parse_next(&mut &*xml).ok()
+}
+
+fn versions(input: &mut &str) -> winnow::Result<Vec<String>> {
+    preceded(
+        (take_until(0.., "<versions>"), "<versions>"),
+        terminated(repeat(0.., version), (multispace0, "</versions>")),
+    )
+    .parse_next(input)
+}
+
+fn version(input: &mut &str) -> winnow::Result<String> {
+    delimited(
+        (multispace0, "<version>"),
+        take_until(0.., "</version>"),
+        "</version>",
+    )
+    .map(|version: &str| version.trim().to_string())
+    .parse_next(input)
+}
+
+/// Releases of `jruby-dist` on Maven Central
+///
+/// The metadata also lists prereleases (`9.0.0.0.rc1`) and the three segment versions of
+/// JRuby 1.x. Those aren't [`JRubyVersion`]s and are skipped rather than reported.
+pub async fn jruby_maven_versions() -> Result<Vec<JRubyVersion>, Error> {
+    shared::with_retries(jruby_maven_versions_inner).await
+}
+
+async fn jruby_maven_versions_inner() -> Result<Vec<JRubyVersion>, Error> {
+    let response = shared::http::client()?
+        .get(JRUBY_DIST_METADATA_URL)
+        .timeout(RequestClass::Api.timeout())
+        .send()
+        .await
+        .map_err(Error::FailedRequest)?;
+
+    let body = shared::http::error_for_status(response)?
+        .text()
+        .await
+        .map_err(Error::FailedRequest)?;
+
+    releases_from_metadata(&body).ok_or_else(|| Error::CannotParseMavenMetadata {
+        url: JRUBY_DIST_METADATA_URL.to_string(),
+        body,
+    })
+}
+
+fn releases_from_metadata(xml: &str) -> Option<Vec<JRubyVersion>> {
+    Some(
+        parse_metadata_versions(xml)?
+            .iter()
+            .filter_map(|version| JRubyVersion::parse(version).ok())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>org.jruby</groupId>
+  <artifactId>jruby-dist</artifactId>
+  <versioning>
+    <latest>10.0.2.0</latest>
+    <release>10.0.2.0</release>
+    <versions>
+      <version>1.7.0.preview1</version>
+      <version>9.0.0.0.rc1</version>
+      <version>9.4.7.0</version>
+      <version>10.0.2.0</version>
+    </versions>
+    <lastUpdated>20250804150102</lastUpdated>
+  </versioning>
+</metadata>
+"#;
+
+    #[test]
+    fn parses_all_listed_versions() {
+        assert_eq!(
+            parse_metadata_versions(METADATA).unwrap(),
+            vec!["1.7.0.preview1", "9.0.0.0.rc1", "9.4.7.0", "10.0.2.0"]
+        );
+    }
+
+    #[test]
+    fn keeps_only_release_versions() {
+        let names = releases_from_metadata(METADATA)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["9.4.7.0", "10.0.2.0"]);
+    }
+
+    #[test]
+    fn empty_versions_list() {
+        let xml = "<metadata><versioning><versions>\n</versions></versioning></metadata>";
+        assert_eq!(parse_metadata_versions(xml), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_documents_without_versions() {
+        assert_eq!(parse_metadata_versions("<html>Not Found</html>"), None);
+        assert_eq!(
+            parse_metadata_versions("<versions><version>9.4.7.0</version>"),
+            None
+        );
+    }
+}
@@ -0,0 +1,669 @@
+//! Checking GitHub and Maven Central for JRuby releases that aren't on S3 yet
+//!
+//! See [`shared::release_check`] for the pipeline this plugs into.
+
+use crate::maven::{JRUBY_DIST_METADATA_URL, jruby_maven_versions};
+use crate::{JRubyVersion, jruby_build_properties, jruby_version};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use shared::github::{self, GitHubAuth, GitHubResponse, Page, PageError, ResponseCache};
+use shared::maybe_err::ResultVec;
+use shared::release_check::{BoxError, CheckError, ReleaseEngine, Releases};
+use shared::{Compression, settle};
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use url::Url;
+
+pub static RELEASES_URL: std::sync::LazyLock<Url> = std::sync::LazyLock::new(|| {
+    // per_page=100 is the GitHub releases API maximum page size.
+    Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100")
+        .expect("valid releases URL constant")
+});
+
+/// Where to discover JRuby releases
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum ReleaseSource {
+    /// GitHub releases of jruby/jruby
+    Github,
+    /// `org.jruby:jruby-dist` on Maven Central, which `jruby_build` downloads from
+    Maven,
+    /// Only versions listed by both, warning about the rest
+    Reconcile,
+}
+
+/// JRuby releases at or above `minimum_version` that have settled
+#[derive(Debug)]
+pub struct JRubyEngine {
+    pub minimum_version: JRubyVersion,
+    pub release_source: ReleaseSource,
+    pub auth: GitHubAuth,
+    pub cache: Option<ResponseCache>,
+    /// See [`shared::settle`]. Only applies to GitHub releases, Maven Central metadata
+    /// doesn't say when each version was published.
+    pub settle_window: TimeDelta,
+    /// Compression of the artifacts to look for, see [`Compression::extension`]
+    pub compression: Compression,
+}
+
+impl ReleaseEngine for JRubyEngine {
+    type Version = JRubyVersion;
+
+    fn name(&self) -> &'static str {
+        "JRuby"
+    }
+
+    async fn releases(&self) -> Releases<JRubyVersion> {
+        let mut releases = Releases::default();
+        let versions = match self.release_source {
+            ReleaseSource::Github => self.github_releases(&mut releases).await,
+            ReleaseSource::Maven => maven_releases(&mut releases).await,
+            ReleaseSource::Reconcile => {
+                let github = retain_releases_gte(
+                    &self.github_releases(&mut releases).await,
+                    &self.minimum_version,
+                );
+                let maven = retain_releases_gte(
+                    &maven_releases(&mut releases).await,
+                    &self.minimum_version,
+                );
+
+                let Reconciled {
+                    both,
+                    github_only,
+                    maven_only,
+                } = reconcile(&github, &maven);
+                if !github_only.is_empty() {
+                    releases.notes.push(format!(
+                        "Skipping versions released on GitHub but not yet on Maven Central: {}",
+                        join_versions(&github_only)
+                    ));
+                }
+                if !maven_only.is_empty() {
+                    releases.notes.push(format!(
+                        "Skipping versions on Maven Central without a GitHub release: {}",
+                        join_versions(&maven_only)
+                    ));
+                }
+                both
+            }
+        };
+
+        releases.versions = retain_releases_gte(&versions, &self.minimum_version);
+        releases
+    }
+
+    async fn artifact_name(&self, version: &JRubyVersion) -> Result<String, BoxError> {
+        let stdlib = jruby_build_properties(version)
+            .await
+            .and_then(|props| props.ruby_stdlib_version())?;
+        Ok(artifact_file_name(version, &stdlib, self.compression))
+    }
+}
+
+impl JRubyEngine {
+    /// Settled, non-prerelease versions from the GitHub releases API
+    async fn github_releases(&self, releases: &mut Releases<JRubyVersion>) -> Vec<JRubyVersion> {
+        let auth = &self.auth;
+        let cache = self.cache.as_ref();
+        let rate_limit = Mutex::new(None);
+        let last_rate_limit = &rate_limit;
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let listed = paginate_releases_accumulated(RELEASES_URL.clone(), |url| async move {
+            let response = github::get_with_auth_retry_and_cache(&url, auth, cache).await?;
+            if let Some(rate_limit) = response.rate_limit() {
+                *last_rate_limit.lock().expect("not poisoned") = Some(rate_limit);
+            }
+            Ok(response)
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+        releases.errors.extend(
+            errors
+                .into_iter()
+                .map(|error| CheckError::new("listing GitHub releases", error)),
+        );
+        if let Some(rate_limit) = rate_limit.into_inner().expect("not poisoned") {
+            releases
+                .notes
+                .push(format!("GitHub rate limit: {rate_limit}"));
+        }
+
+        let now = Utc::now();
+        let mut settled = Vec::new();
+        for release in listed {
+            match release.unsettled(now, self.settle_window) {
+                None => settled.push(release.version),
+                Some(reason) if release.version >= self.minimum_version => releases
+                    .notes
+                    .push(format!("{}: skipping, {reason}", release.version)),
+                Some(_) => {}
+            }
+        }
+        settled
+    }
+}
+
+/// Release versions of `jruby-dist` on Maven Central, where `jruby_build` downloads from
+async fn maven_releases(releases: &mut Releases<JRubyVersion>) -> Vec<JRubyVersion> {
+    jruby_maven_versions().await.unwrap_or_else(|error| {
+        releases.errors.push(CheckError::new(
+            format!("fetching releases from {JRUBY_DIST_METADATA_URL}"),
+            error,
+        ));
+        Vec::new()
+    })
+}
+
+/// File name of the JRuby build for `version`, which implements Ruby `ruby_stdlib_version`
+pub fn artifact_file_name(
+    version: &JRubyVersion,
+    ruby_stdlib_version: &str,
+    compression: Compression,
+) -> String {
+    format!(
+        "ruby-{ruby_stdlib_version}-jruby-{version}{}",
+        compression.extension()
+    )
+}
+
+/// A single entry from the GitHub releases listing API.
+///
+/// Only the fields needed to discover JRuby versions are deserialized; the rest
+/// of the payload is ignored.
+#[derive(Deserialize)]
+struct RawGitHubRelease {
+    /// The release's git tag (e.g. `"9.4.15.0"`), optionally prefixed with `v`.
+    /// Parsed into a [`JRubyVersion`] after stripping the leading `v`.
+    tag_name: String,
+    /// Whether GitHub flagged this as a prerelease. Prereleases are filtered out
+    /// so only stable versions are considered.
+    prerelease: bool,
+    /// Drafts are only listed for tokens with push access and aren't public yet.
+    #[serde(default)]
+    draft: bool,
+    /// When the release was made public, `null` for drafts.
+    #[serde(default)]
+    published_at: Option<DateTime<Utc>>,
+    /// Files attached to the release.
+    #[serde(default)]
+    assets: Vec<RawGitHubAsset>,
+}
+
+/// A file attached to a GitHub release.
+#[derive(Deserialize)]
+struct RawGitHubAsset {
+    name: String,
+    /// `"uploaded"` once the file is available, `"open"` while it is still being uploaded.
+    state: String,
+}
+
+/// A non-prerelease JRuby release along with what's needed to tell whether it
+/// has been public long enough to build.
+#[derive(Debug, Clone)]
+struct JRubyRelease {
+    version: JRubyVersion,
+    draft: bool,
+    published_at: Option<DateTime<Utc>>,
+    /// Names of attached assets that haven't finished uploading.
+    uploading_assets: Vec<String>,
+}
+
+/// Why a release is held back from being built for now
+#[derive(Debug, PartialEq)]
+enum Unsettled {
+    Draft,
+    Unpublished,
+    UploadingAssets(Vec<String>),
+    TooRecent(TimeDelta),
+}
+
+impl fmt::Display for Unsettled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsettled::Draft => write!(f, "draft release"),
+            Unsettled::Unpublished => write!(f, "not published yet"),
+            Unsettled::UploadingAssets(names) => {
+                write!(f, "assets still uploading: {}", names.join(", "))
+            }
+            Unsettled::TooRecent(remaining) => write!(
+                f,
+                "published recently, settles in {}",
+                settle::format_remaining(*remaining)
+            ),
+        }
+    }
+}
+
+impl JRubyRelease {
+    fn from_raw(release: RawGitHubRelease) -> Result<Self, jruby_version::ParseError> {
+        let tag = release
+            .tag_name
+            .strip_prefix('v')
+            .unwrap_or(&release.tag_name);
+
+        Ok(JRubyRelease {
+            version: JRubyVersion::parse(tag)?,
+            draft: release.draft,
+            published_at: release.published_at,
+            uploading_assets: release
+                .assets
+                .into_iter()
+                .filter(|asset| asset.state != "uploaded")
+                .map(|asset| asset.name)
+                .collect(),
+        })
+    }
+
+    /// `None` when the release is public, fully uploaded, and was published at
+    /// least `window` before `now`
+    fn unsettled(&self, now: DateTime<Utc>, window: TimeDelta) -> Option<Unsettled> {
+        if self.draft {
+            return Some(Unsettled::Draft);
+        }
+        let Some(published_at) = self.published_at else {
+            return Some(Unsettled::Unpublished);
+        };
+        if !self.uploading_assets.is_empty() {
+            return Some(Unsettled::UploadingAssets(self.uploading_assets.clone()));
+        }
+        settle::settle_remaining(published_at, now, window).map(Unsettled::TooRecent)
+    }
+}
+
+/// A page of the releases listing failed, or a release on it had an unparsable tag.
+///
+/// Pagination cannot continue past a failed page (the `next` link lives in the
+/// failed response), so this is returned alongside whatever releases were
+/// collected from earlier pages rather than discarding them.
+#[derive(Debug, thiserror::Error)]
+#[error("failed fetching releases page {url}: {source}")]
+pub struct ReleasePageError {
+    url: Url,
+    source: GithubReleaseError,
+}
+
+/// Walk the release pages with [`github::paginate`], accumulating parsed
+/// releases and any errors encountered along the way.
+///
+/// `fetch` is injected so the pagination/partial-success logic can be exercised
+/// without real network access. Prereleases are skipped, and versions that fail
+/// to parse are collected as errors rather than dropped.
+async fn paginate_releases_accumulated<F, Fut>(
+    base_url: Url,
+    fetch: F,
+) -> ResultVec<JRubyRelease, ReleasePageError>
+where
+    F: FnMut(Url) -> Fut,
+    Fut: Future<Output = Result<GitHubResponse, github::GitHubError>>,
+{
+    let pages =
+        github::paginate::<RawGitHubRelease, _, _>(base_url, github::DEFAULT_MAX_PAGES, fetch);
+    let mut pages = std::pin::pin!(pages);
+
+    let mut results: Vec<Result<JRubyRelease, ReleasePageError>> = Vec::new();
+    while let Some(page) = pages.next().await {
+        match page {
+            Ok(Page { url, items }) => {
+                for release in items.into_iter().filter(|r| !r.prerelease) {
+                    results.push(JRubyRelease::from_raw(release).map_err(|error| {
+                        ReleasePageError {
+                            url: url.clone(),
+                            source: GithubReleaseError::CannotParseJrubyVersion(error),
+                        }
+                    }))
+                }
+            }
+            Err(PageError { url, source }) => results.push(Err(ReleasePageError {
+                url,
+                source: source.into(),
+            })),
+        }
+    }
+
+    results.into()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GithubReleaseError {
+    #[error(transparent)]
+    Page(#[from] github::PageErrorKind),
+
+    #[error(transparent)]
+    CannotParseJrubyVersion(#[from] jruby_version::ParseError),
+}
+
+/// Keep only the releases at or above `minimum`, narrowing the full release
+/// listing down to the versions worth checking on S3.
+///
+/// Ordering uses [`JRubyVersion`]'s field-wise comparison (major, then minor,
+/// then patch, then extra), so `9.4.7.0` and anything newer is retained while
+/// older versions are dropped. The input slice is left untouched; matching
+/// versions are cloned into the returned vector.
+pub fn retain_releases_gte(releases: &[JRubyVersion], minimum: &JRubyVersion) -> Vec<JRubyVersion> {
+    releases
+        .iter()
+        .filter(|version| *version >= minimum)
+        .cloned()
+        .collect()
+}
+
+/// Releases split by which source(s) list them
+#[derive(Debug, PartialEq)]
+struct Reconciled {
+    both: Vec<JRubyVersion>,
+    github_only: Vec<JRubyVersion>,
+    maven_only: Vec<JRubyVersion>,
+}
+
+/// Compare the GitHub and Maven Central listings, preserving each one's order
+fn reconcile(github: &[JRubyVersion], maven: &[JRubyVersion]) -> Reconciled {
+    let (both, github_only) = github
+        .iter()
+        .cloned()
+        .partition(|version| maven.contains(version));
+    let maven_only = maven
+        .iter()
+        .filter(|version| !github.contains(version))
+        .cloned()
+        .collect();
+
+    Reconciled {
+        both,
+        github_only,
+        maven_only,
+    }
+}
+
+fn join_versions(versions: &[JRubyVersion]) -> String {
+    versions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retain_releases_gte() {
+        let releases = vec![
+            JRubyVersion::parse("10.1.0.0").unwrap(),
+            JRubyVersion::parse("9.4.15.0").unwrap(),
+            JRubyVersion::parse("9.4.7.0").unwrap(),
+            JRubyVersion::parse("9.4.0.0").unwrap(),
+            JRubyVersion::parse("9.3.0.0").unwrap(),
+        ];
+        let min = JRubyVersion::parse("9.4.7.0").unwrap();
+        let filtered = retain_releases_gte(&releases, &min);
+        let names: Vec<String> = filtered.iter().map(|v| v.to_string()).collect();
+        assert_eq!(names, vec!["10.1.0.0", "9.4.15.0", "9.4.7.0"]);
+    }
+
+    #[test]
+    fn test_artifact_file_name() {
+        let version = JRubyVersion::parse("9.4.7.0").unwrap();
+        assert_eq!(
+            artifact_file_name(&version, "3.1.4", Compression::Gzip),
+            "ruby-3.1.4-jruby-9.4.7.0.tgz"
+        );
+        assert_eq!(
+            artifact_file_name(&version, "3.1.4", Compression::Zstd),
+            "ruby-3.1.4-jruby-9.4.7.0.tar.zst"
+        );
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let versions = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| JRubyVersion::parse(name).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let github = versions(&["10.0.3.0", "10.0.2.0", "9.4.14.0"]);
+        let maven = versions(&["9.4.13.0", "9.4.14.0", "10.0.2.0"]);
+
+        assert_eq!(
+            reconcile(&github, &maven),
+            Reconciled {
+                both: versions(&["10.0.2.0", "9.4.14.0"]),
+                github_only: versions(&["10.0.3.0"]),
+                maven_only: versions(&["9.4.13.0"]),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn paginate_strips_leading_v_from_tag() {
+        let page1 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100").unwrap();
+
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let versions = paginate_releases_accumulated(page1, move |_url| async move {
+            Ok(response(&releases_json(&["v9.4.7.0"]), None))
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        let names: Vec<String> = versions.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(names, vec!["9.4.7.0"]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_github_release() {
+        let json = r#"{"tag_name": "9.4.15.0", "prerelease": false}"#;
+        let release: RawGitHubRelease = serde_json::from_str(json).unwrap();
+        assert_eq!(release.tag_name, "9.4.15.0");
+        assert!(!release.prerelease);
+    }
+
+    #[test]
+    fn test_deserialize_github_release_metadata() {
+        let json = r#"{
+            "tag_name": "10.0.2.0",
+            "prerelease": false,
+            "draft": false,
+            "published_at": "2025-08-04T15:01:02Z",
+            "assets": [
+                {"name": "jruby-bin-10.0.2.0.tar.gz", "state": "uploaded"},
+                {"name": "jruby-bin-10.0.2.0.zip", "state": "open"}
+            ]
+        }"#;
+        let release = JRubyRelease::from_raw(serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(release.version.to_string(), "10.0.2.0");
+        assert!(!release.draft);
+        assert_eq!(
+            release.published_at.unwrap().to_rfc3339(),
+            "2025-08-04T15:01:02+00:00"
+        );
+        assert_eq!(release.uploading_assets, vec!["jruby-bin-10.0.2.0.zip"]);
+    }
+
+    #[test]
+    fn test_release_settling() {
+        let published_at = DateTime::parse_from_rfc3339("2025-08-04T15:00:00Z")
+            .unwrap()
+            .to_utc();
+        let release = JRubyRelease {
+            version: JRubyVersion::parse("10.0.2.0").unwrap(),
+            draft: false,
+            published_at: Some(published_at),
+            uploading_assets: Vec::new(),
+        };
+        let window = TimeDelta::hours(1);
+
+        assert_eq!(
+            release.unsettled(published_at + TimeDelta::minutes(15), window),
+            Some(Unsettled::TooRecent(TimeDelta::minutes(45)))
+        );
+        assert_eq!(
+            release.unsettled(published_at + TimeDelta::hours(2), window),
+            None
+        );
+
+        let uploading = JRubyRelease {
+            uploading_assets: vec!["jruby-bin-10.0.2.0.zip".to_string()],
+            ..release.clone()
+        };
+        assert_eq!(
+            uploading.unsettled(published_at + TimeDelta::days(1), window),
+            Some(Unsettled::UploadingAssets(vec![
+                "jruby-bin-10.0.2.0.zip".to_string()
+            ]))
+        );
+
+        let draft = JRubyRelease {
+            draft: true,
+            published_at: None,
+            ..release
+        };
+        assert_eq!(
+            draft.unsettled(published_at + TimeDelta::days(1), window),
+            Some(Unsettled::Draft)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_github_release_prerelease() {
+        let json = r#"{"tag_name": "9.5.0.0.pre1", "prerelease": true}"#;
+        let release: RawGitHubRelease = serde_json::from_str(json).unwrap();
+        assert!(release.prerelease);
+    }
+
+    #[test]
+    fn test_releases_url_sets_per_page() {
+        assert!(
+            RELEASES_URL.as_str().contains("per_page=100"),
+            "got: {}",
+            RELEASES_URL.as_str()
+        );
+    }
+
+    fn releases_json(tags: &[&str]) -> String {
+        serde_json::to_string(
+            &tags
+                .iter()
+                .map(|tag| serde_json::json!({"tag_name": tag, "prerelease": false}))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    fn response(body: &str, next: Option<&Url>) -> GitHubResponse {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(next) = next {
+            headers.insert(
+                reqwest::header::LINK,
+                format!(r#"<{next}>; rel="next""#).parse().unwrap(),
+            );
+        }
+        GitHubResponse {
+            headers,
+            body: body.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn paginate_keeps_releases_collected_before_a_failed_page() {
+        let page1 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100").unwrap();
+        let page2 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100&page=2")
+                .unwrap();
+        let expected_failed = page2.clone();
+
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let versions = paginate_releases_accumulated(page1, move |url| {
+            let page2 = page2.clone();
+            async move {
+                if url.as_str().contains("page=2") {
+                    Ok(response("not json", None))
+                } else {
+                    Ok(response(
+                        &releases_json(&["9.4.15.0", "9.4.14.0"]),
+                        Some(&page2),
+                    ))
+                }
+            }
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        let names: Vec<String> = versions.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(names, vec!["9.4.15.0", "9.4.14.0"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].url, expected_failed);
+    }
+
+    #[tokio::test]
+    async fn paginate_returns_no_error_when_all_pages_succeed() {
+        let page1 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100").unwrap();
+
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let versions = paginate_releases_accumulated(page1, move |_url| async move {
+            Ok(response(&releases_json(&["9.4.15.0"]), None))
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        assert_eq!(versions.len(), 1);
+        assert!(errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn paginate_skips_prereleases() {
+        let page1 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100").unwrap();
+
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let versions = paginate_releases_accumulated(page1, move |_url| async move {
+            Ok(response(
+                r#"[
+                    {"tag_name": "9.5.0.0.pre1", "prerelease": true},
+                    {"tag_name": "9.4.15.0", "prerelease": false}
+                ]"#,
+                None,
+            ))
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        let names: Vec<String> = versions.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(names, vec!["9.4.15.0"]);
+        assert!(errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn paginate_accumulates_version_parse_errors_and_keeps_collecting() {
+        let page1 =
+            Url::parse("https://api.github.com/repos/jruby/jruby/releases?per_page=100").unwrap();
+
+        let mut errors: Vec<ReleasePageError> = Vec::new();
+        let versions = paginate_releases_accumulated(page1, move |_url| async move {
+            Ok(response(
+                &releases_json(&["9.4.15.0", "not-a-version"]),
+                None,
+            ))
+        })
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        let names: Vec<String> = versions.iter().map(|r| r.version.to_string()).collect();
+        assert_eq!(names, vec!["9.4.15.0"]);
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(
+                errors[0].source,
+                GithubReleaseError::CannotParseJrubyVersion(_)
+            ),
+            "got: {:?}",
+            errors[0]
+        );
+    }
+}
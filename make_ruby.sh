@@ -41,6 +41,11 @@ debugflags=-g ./configure "${configure_opts[@]}" --prefix /tmp/compiled
 make -j"$(nproc)"
 make install
 
-# Compress and store the compiled ruby
+# pkg-config files record the absolute `--prefix`, point them at their own location instead
+# so they keep working wherever the tarball is extracted
+# shellcheck disable=SC2016 # `${pcfiledir}` is for pkg-config, not the shell
+sed -i 's|^prefix=.*|prefix=${pcfiledir}/../..|' /tmp/compiled/lib/pkgconfig/*.pc
+
+# Compress and store the compiled ruby, the format (gzip, zstd or xz) follows the file extension
 cd /tmp/compiled
-tar -czf "$OUT_TAR" .
+tar -caf "$OUT_TAR" .
@@ -0,0 +1,155 @@
+//! Check Ruby and JRuby for releases that aren't pushed to S3 yet, in one report
+//!
+//! ```term
+//! $ cargo run --bin release_check -- --help
+//! ```
+
+use bullet_stream::global::print;
+use chrono::TimeDelta;
+use clap::Parser;
+use fs_err as fs;
+use jruby_executable::JRubyVersion;
+use jruby_executable::release_check::{JRubyEngine, ReleaseSource};
+use ruby_executable::release_check::RubyEngine;
+use shared::github::{GitHubAuth, GitHubToken, ResponseCache};
+use shared::release_check::{self, Report};
+use shared::summary;
+use shared::{Compression, RubyDownloadVersion};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[command(about = "Check for Ruby and JRuby releases missing from Heroku S3")]
+struct Args {
+    /// Minimum Ruby version to check (e.g. 3.2.0)
+    #[arg(long = "ruby-minimum-version", required = true)]
+    ruby_minimum_version: RubyDownloadVersion,
+
+    /// Minimum JRuby version to check (e.g. 9.4.7.0)
+    #[arg(long = "jruby-minimum-version", required = true)]
+    jruby_minimum_version: JRubyVersion,
+
+    /// Where to discover JRuby releases
+    #[arg(long = "jruby-release-source", value_enum, default_value = "github")]
+    jruby_release_source: ReleaseSource,
+
+    /// Only build releases published at least this many minutes ago
+    #[arg(long = "settle-minutes", default_value_t = 60)]
+    settle_minutes: u32,
+
+    /// Compression of the artifacts to look for on S3
+    #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+    compression: Compression,
+
+    /// GitHub API token used to list JRuby releases. Prefer `--gh-token-file` or the
+    /// `GITHUB_TOKEN`/`GH_TOKEN` environment variables.
+    #[arg(long = "gh-token", value_parser = |s: &str| -> Result<GitHubToken, String> {
+        GitHubToken::try_from(s).map_err(|error| format!("{error}. Suggestion: set `GITHUB_TOKEN` or log in with `gh auth login`"))
+    })]
+    gh_token: Option<GitHubToken>,
+
+    /// File containing the GitHub API token
+    #[arg(long = "gh-token-file", conflicts_with = "gh_token")]
+    gh_token_file: Option<PathBuf>,
+
+    /// Directory to cache GitHub responses in
+    #[arg(long = "github-cache-dir")]
+    github_cache_dir: Option<PathBuf>,
+
+    /// Path to write the JSON report to
+    #[arg(long = "report-json", required = true)]
+    report_json: PathBuf,
+
+    /// Path to write the Markdown report to
+    #[arg(long = "report-markdown")]
+    report_markdown: Option<PathBuf>,
+
+    /// Append the Markdown report to this file, e.g. `$GITHUB_STEP_SUMMARY`
+    #[arg(long = "summary-markdown")]
+    summary_markdown: Option<PathBuf>,
+}
+
+async fn call(args: Args) -> Result<Report, Box<dyn Error>> {
+    print::h2("Checking for new Ruby and JRuby releases");
+    print::bullet(format!(
+        "Ruby minimum version: {}",
+        args.ruby_minimum_version
+    ));
+    print::bullet(format!(
+        "JRuby minimum version: {}",
+        args.jruby_minimum_version
+    ));
+
+    let auth = GitHubAuth::resolve_and_log(args.gh_token.clone(), args.gh_token_file.as_deref())?;
+
+    let settle_window = TimeDelta::minutes(i64::from(args.settle_minutes));
+    let ruby = RubyEngine {
+        minimum_version: args.ruby_minimum_version,
+        settle_window,
+        compression: args.compression,
+    };
+    let jruby = JRubyEngine {
+        minimum_version: args.jruby_minimum_version,
+        release_source: args.jruby_release_source,
+        auth,
+        cache: args.github_cache_dir.as_deref().map(ResponseCache::new),
+        settle_window,
+        compression: args.compression,
+    };
+
+    let (ruby, jruby) = tokio::join!(
+        release_check::check(Arc::new(ruby)),
+        release_check::check(Arc::new(jruby))
+    );
+    let report = Report {
+        engines: vec![ruby, jruby],
+    };
+
+    for engine in &report.engines {
+        print::bullet(format!(
+            "{}: checked {} versions, {} need builds",
+            engine.engine,
+            engine.versions.len(),
+            engine.to_build().len()
+        ));
+        for version in engine.to_build() {
+            print::sub_bullet(version);
+        }
+    }
+
+    fs::write(&args.report_json, report.to_json())?;
+    print::bullet(format!("Wrote {}", args.report_json.display()));
+    if let Some(path) = &args.report_markdown {
+        fs::write(path, report.to_markdown())?;
+        print::bullet(format!("Wrote {}", path.display()));
+    }
+    summary::append_markdown(args.summary_markdown.as_deref(), &report.to_markdown())?;
+
+    Ok(report)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    match call(args).await {
+        Ok(report) if !report.has_errors() => print::bullet("Done"),
+        Ok(report) => {
+            print::error(format!(
+                "Failed! Errors:\n{}",
+                report
+                    .engines
+                    .iter()
+                    .flat_map(|engine| engine.errors.iter())
+                    .map(|error| format!("- {error}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+            std::process::exit(1);
+        }
+        Err(error) => {
+            print::error(format!("Failed! {error}"));
+            std::process::exit(1);
+        }
+    }
+}
@@ -5,11 +5,17 @@ use indoc::formatdoc;
 use libherokubuildpack::inventory::artifact::Arch;
 use reqwest::Url;
 use shared::{
-    BaseImage, BuildStatus, RubyDownloadVersion, S3_BASE_URL, TarDownloadPath,
-    append_filename_with, download_tar, output_ruby_tar_path, s3_url_exists, sha256_from_path,
-    source_dir, write_job_metadata,
+    BaseImage, BuildStatus, Compression, RubyDownloadVersion, S3_BASE_URL, TarDownloadPath,
+    append_filename_with, audit, download_tar, http,
+    job_result::{BuildOutput, JobResult, OutputFile, tool_version},
+    output_ruby_tar_path,
+    provenance::{self, BuildInputs},
+    ruby_tar_file_name, s3_url_exists, sha256_from_path, source_dir,
+    summary::{BuildSummary, append_markdown},
+    update_shebangs_in_tar, write_job_metadata,
 };
 use std::{
+    collections::BTreeMap,
     io::Write,
     path::{Path, PathBuf},
     process::Command,
@@ -47,6 +53,18 @@ struct RubyArgs {
 
     #[arg(long = "job-metadata")]
     job_metadata: Option<PathBuf>,
+
+    /// Compression for the output tarball, anything other than gzip is not yet downloaded by the buildpack
+    #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+    compression: Compression,
+
+    /// Write a JSON document describing the build's outputs, checksums and tools to this file
+    #[arg(long = "job-result")]
+    job_result: Option<PathBuf>,
+
+    /// Append a Markdown table of the build's outcome to this file, e.g. `$GITHUB_STEP_SUMMARY`
+    #[arg(long = "summary-markdown")]
+    summary_markdown: Option<PathBuf>,
 }
 
 fn ruby_dockerfile_path() -> PathBuf {
@@ -56,7 +74,7 @@ fn ruby_dockerfile_path() -> PathBuf {
         .join("Dockerfile")
 }
 
-async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::Error>> {
+async fn ruby_build(args: &RubyArgs) -> Result<BuildOutput, Box<dyn std::error::Error>> {
     let RubyArgs {
         arch,
         version,
@@ -65,17 +83,32 @@ async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::
         artifact_dir,
         cache_dir,
         job_metadata: _,
+        compression,
+        job_result: _,
+        summary_markdown: _,
     } = args;
 
     let start = Instant::now();
     print::h2("Building Ruby");
+    let skipped = || BuildOutput {
+        status: BuildStatus::Skipped,
+        artifact_name: ruby_tar_file_name(version, *compression),
+        outputs: Vec::new(),
+        tools: BTreeMap::new(),
+    };
     let volume_cache_dir = cache_dir;
     let volume_output_dir = artifact_dir;
 
     fs::create_dir_all(volume_cache_dir)?;
     fs::create_dir_all(volume_output_dir)?;
 
-    let expected_output = output_ruby_tar_path(volume_output_dir, version, base_image, Some(arch));
+    let expected_output = output_ruby_tar_path(
+        volume_output_dir,
+        version,
+        base_image,
+        Some(arch),
+        *compression,
+    );
 
     match on_conflict {
         OnConflict::Skip => {
@@ -84,7 +117,7 @@ async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::
                     "Output already exists locally: {}, skipping",
                     expected_output.display()
                 ));
-                return Ok(BuildStatus::Skipped);
+                return Ok(skipped());
             }
 
             let s3_path = expected_output.strip_prefix(volume_output_dir)?;
@@ -97,9 +130,9 @@ async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::
             };
 
             print::bullet(format!("Checking if already uploaded: {url}"));
-            if s3_url_exists(url.clone()).await? {
+            if s3_url_exists(http::client()?, url.clone()).await? {
                 print::bullet(format!("Already exists: {url}, skipping"));
-                return Ok(BuildStatus::Skipped);
+                return Ok(skipped());
             }
         }
         OnConflict::Overwrite => {}
@@ -138,12 +171,31 @@ async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::
             "Downloading {version} to {}",
             download_tar_path.as_ref().display()
         ));
-        download_tar(&version.download_url(), &download_tar_path).await?;
+        download_tar(http::client()?, &version.download_url(), &download_tar_path).await?;
+    };
+
+    let inputs = BuildInputs {
+        version: version.to_string(),
+        base_image: base_image.to_string(),
+        arch: Some(arch.to_string()),
+        source_url: version.download_url(),
+        source_sha256: sha256_from_path(download_tar_path.as_ref())?,
+        dockerfile_sha256: Some(sha256_from_path(&dockerfile_path)?),
+        configure_options: provenance::configure_options(&fs::read_to_string(
+            source_dir().join("make_ruby.sh"),
+        )?),
+        builder_commit: provenance::builder_commit(),
     };
 
     print::bullet("Make Ruby");
     let input_tar = PathBuf::from(INNER_CACHE).join(format!("ruby-source-{version}.tgz"));
-    let output_tar = output_ruby_tar_path(Path::new(INNER_OUTPUT), version, base_image, Some(arch));
+    let output_tar = output_ruby_tar_path(
+        Path::new(INNER_OUTPUT),
+        version,
+        base_image,
+        Some(arch),
+        *compression,
+    );
     let volume_cache = volume_cache_dir.display();
     let volume_output = volume_output_dir.display();
 
@@ -164,38 +216,114 @@ async fn ruby_build(args: &RubyArgs) -> Result<BuildStatus, Box<dyn std::error::
 
     print::sub_stream_cmd(docker_run)?;
 
-    let output_tar = output_ruby_tar_path(volume_output_dir, version, base_image, Some(arch));
+    let output_tar = output_ruby_tar_path(
+        volume_output_dir,
+        version,
+        base_image,
+        Some(arch),
+        *compression,
+    );
+
+    print::bullet("Rewrite Ruby shebangs");
+    let updated = update_shebangs_in_tar(&output_tar, *compression)?;
+    for path in &updated {
+        print::sub_bullet(format!("Rewrote {}", path.display()));
+    }
+    if updated.is_empty() {
+        print::sub_bullet("None found");
+    }
+
+    print::bullet(format!("Audit {}", output_tar.display()));
+    for warning in audit::audit_tarball(&output_tar)?.into_result()? {
+        print::sub_bullet(format!("Warning: {warning}"));
+    }
 
-    let sha_seven_path = cp_file_sha_seven_same_dir(&output_tar)?;
+    let mut outputs = vec![cp_file_sha_seven_same_dir(
+        &output_tar,
+        *compression,
+        &inputs,
+    )?];
 
-    print::sub_bullet(format!("Copied SHA tgz {}", sha_seven_path.display(),));
+    if let Some(sha_seven_path) = &outputs[0].sha_seven_path {
+        print::sub_bullet(format!("Copied SHA tarball {}", sha_seven_path.display(),));
+    }
 
     if base_image.has_legacy_path() {
-        let legacy_output = output_ruby_tar_path(volume_output_dir, version, base_image, None);
+        let legacy_output =
+            output_ruby_tar_path(volume_output_dir, version, base_image, None, *compression);
         fs::copy(expected_output, &legacy_output)?;
-        cp_file_sha_seven_same_dir(&legacy_output)?;
+        outputs.push(cp_file_sha_seven_same_dir(
+            &legacy_output,
+            *compression,
+            &inputs,
+        )?);
     }
 
     print::all_done(&Some(start));
 
-    Ok(BuildStatus::Success)
+    let mut tools = BTreeMap::from([(
+        "ruby_build".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+    )]);
+    tools.extend(tool_version("docker").map(|version| ("docker".to_string(), version)));
+    Ok(BuildOutput {
+        status: BuildStatus::Success,
+        artifact_name: ruby_tar_file_name(version, *compression),
+        outputs,
+        tools,
+    })
 }
 
-fn cp_file_sha_seven_same_dir(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+/// Copies `path` to a SHA suffixed file and writes provenance covering both
+fn cp_file_sha_seven_same_dir(
+    path: &Path,
+    compression: Compression,
+    inputs: &BuildInputs,
+) -> Result<OutputFile, Box<dyn std::error::Error>> {
     let sha = sha256_from_path(path)?;
     let sha_seven = sha.chars().take(7).collect::<String>();
-    let sha_seven_path = append_filename_with(path, &format!("-{sha_seven}"), ".tgz")?;
+    let sha_seven_path =
+        append_filename_with(path, &format!("-{sha_seven}"), compression.extension())?;
     fs::copy(path, &sha_seven_path)?;
-    Ok(sha_seven_path)
+    let provenance_path = inputs.write_for(path, &sha, &[&sha_seven_path])?;
+    Ok(OutputFile {
+        path: path.to_path_buf(),
+        sha256: sha,
+        sha_seven_path: Some(sha_seven_path),
+        provenance_path: Some(provenance_path),
+    })
 }
 
 #[tokio::main]
 async fn main() {
     let args = RubyArgs::parse();
     let metadata = args.job_metadata.as_deref();
-    match ruby_build(&args).await {
-        Ok(status) => {
-            if let Err(e) = write_job_metadata(metadata, "status", status.as_str()) {
+    let start = Instant::now();
+    let result = ruby_build(&args).await;
+    let job_result = JobResult::new(
+        &args.version,
+        &args.base_image,
+        Some(args.arch),
+        &result,
+        start.elapsed(),
+    );
+    if let Err(e) = job_result.write(args.job_result.as_deref()) {
+        print::error(format!("Failed to write job result: {e}"));
+    }
+    let summary = BuildSummary {
+        engine: "Ruby",
+        version: args.version.to_string(),
+        base_image: args.base_image.clone(),
+        arch: Some(args.arch),
+        status: result.as_ref().ok().map(|output| output.status),
+        artifact: Some(ruby_tar_file_name(&args.version, args.compression)),
+    };
+    if let Err(e) = append_markdown(args.summary_markdown.as_deref(), &summary.to_markdown()) {
+        print::error(format!("Failed to write summary: {e}"));
+    }
+    match result {
+        Ok(output) => {
+            if let Err(e) = write_job_metadata(metadata, "status", output.status.as_str()) {
                 print::error(format!("Failed to write job metadata: {e}"));
             }
         }
@@ -1,11 +1,21 @@
 use bullet_stream::global::print;
 use clap::Parser;
+use fs_err::{self as fs, PathExt};
 use indoc::formatdoc;
 use libherokubuildpack::inventory::artifact::Arch;
-use shared::{BaseImage, RubyDownloadVersion, output_ruby_tar_path};
-use std::{error::Error, path::PathBuf, process::Command, time::Instant};
-
-static INNER_OUTPUT: &str = "/tmp/output";
+use ruby_executable::smoke_test::{self, Outcome};
+use shared::{
+    BaseImage, Compression, ImageFlavor, RubyDownloadVersion, TarDownloadPath,
+    audit::BUILD_PREFIX,
+    check_result::{
+        CheckResult, FACTS_COMMANDS, ImageResult, OutputFormat, RuntimeFacts, TestResult,
+    },
+    linkage::{LDD_SCRIPT, LinkageProblem, LinkageReport},
+    output_ruby_tar_path, relocatable, untar_to_dir,
+};
+use std::{
+    error::Error, os::unix::fs::PermissionsExt, path::PathBuf, process::Command, time::Instant,
+};
 
 #[derive(Parser, Debug)]
 struct RubyArgs {
@@ -20,6 +30,22 @@ struct RubyArgs {
 
     #[arg(long = "artifact-dir")]
     artifact_dir: PathBuf,
+
+    /// Compression the tarball was built with
+    #[arg(long, value_enum, default_value_t = Compression::Gzip)]
+    compression: Compression,
+
+    /// Images to run the smoke tests in, repeat to use both
+    #[arg(long = "image-flavor", value_enum, default_values_t = [ImageFlavor::Run])]
+    image_flavor: Vec<ImageFlavor>,
+
+    /// Directory of `*.rb` smoke tests to run, defaults to `smoke_tests/` in this repo
+    #[arg(long = "smoke-tests")]
+    smoke_tests: Option<PathBuf>,
+
+    /// Print the results as Markdown for the GitHub step summary, or as JSON
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown)]
+    format: OutputFormat,
 }
 
 fn ruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
@@ -28,59 +54,207 @@ fn ruby_check(args: &RubyArgs) -> Result<(), Box<dyn Error>> {
         version,
         base_image,
         artifact_dir,
+        compression,
+        image_flavor,
+        smoke_tests: smoke_tests_dir,
+        format,
     } = args;
+    // Docker needs an absolute path to mount
+    let smoke_tests_dir = smoke_tests_dir
+        .clone()
+        .unwrap_or_else(smoke_test::default_suite_dir)
+        .fs_err_canonicalize()?;
+    let smoke_test_names = smoke_test::test_names(&smoke_tests_dir)?;
     let start = Instant::now();
     print::h2(format!(
         "Checking Ruby version ({version} linux/{arch}) for {base_image}",
     ));
-    let path = output_ruby_tar_path(
-        &PathBuf::from(INNER_OUTPUT),
-        version,
-        base_image,
-        Some(arch),
-    );
-    let distro_number = base_image.distro_number();
-
-    let image_name = format!("heroku/heroku:{distro_number}-build");
-    let outside_output = artifact_dir;
-
-    let mut cmd = Command::new("docker");
-    cmd.arg("run");
-    cmd.arg("--rm");
-    cmd.args(["--platform", &format!("linux/{arch}")]);
-    cmd.args([
-        "--volume",
-        &format!(
-            "{outside_output}:{INNER_OUTPUT}",
-            outside_output = outside_output.display()
-        ),
-    ]);
-    cmd.arg(image_name);
-    cmd.args(["bash", "-c"]);
-    cmd.arg(
-        [
-            "mkdir /tmp/unzipped",
-            &format!("tar xzf {} -C /tmp/unzipped", path.display()),
-            "echo -n '- Rubygems version: '",
-            "/tmp/unzipped/bin/gem -v",
-            "echo -n '- Ruby version: '",
-            "/tmp/unzipped/bin/ruby -v",
-        ]
-        .join(" && "),
+    let path = output_ruby_tar_path(artifact_dir, version, base_image, Some(arch), *compression);
+
+    // A random directory that isn't the build prefix catches anything that only works
+    // where Ruby was compiled
+    let extracted = tempfile::Builder::new()
+        .prefix("ruby-relocated-")
+        .tempdir()?;
+    fs::set_permissions(extracted.path(), std::fs::Permissions::from_mode(0o755))?;
+    print::bullet(format!("Extracting to {}", extracted.path().display()));
+    untar_to_dir(&TarDownloadPath(path), extracted.path())?;
+
+    print::bullet(format!("Checking for references to {BUILD_PREFIX}"));
+    let leaks = relocatable::prefix_leaks(extracted.path(), BUILD_PREFIX)?;
+    for leak in &leaks {
+        print::sub_bullet(leak.to_string());
+    }
+    if leaks.is_empty() {
+        print::sub_bullet("None found");
+    }
+
+    let inner_ruby = PathBuf::from("/tmp").join(
+        extracted
+            .path()
+            .file_name()
+            .expect("tempdir has a file name"),
     );
+    let docker_run = || {
+        let mut cmd = Command::new("docker");
+        cmd.arg("run");
+        cmd.arg("--rm");
+        cmd.args(["--platform", &format!("linux/{arch}")]);
+        cmd.args([
+            "--volume",
+            &format!("{}:{}:ro", extracted.path().display(), inner_ruby.display()),
+        ]);
+        cmd
+    };
+
+    let ruby_path = format!("PATH={}/bin:/usr/bin:/bin", inner_ruby.display());
+    let mut check = CheckResult::new("ruby", version, base_image, arch);
+    let mut markdown = format!("## Ruby {version} linux/{arch} for {base_image}\n\n");
+    let mut failures = Vec::new();
+    for flavor in image_flavor {
+        let image_name = base_image.docker_image(*flavor);
+        let mut cmd = docker_run();
+        cmd.args(["--workdir", "/"]);
+        cmd.args(["--env", &ruby_path]);
+        cmd.arg(&image_name);
+        cmd.args(["bash", "-c"]);
+        cmd.arg(
+            ["ruby -e 'puts RUBY_DESCRIPTION' >&2", "gem env >&2"]
+                .into_iter()
+                .chain(FACTS_COMMANDS)
+                .collect::<Vec<_>>()
+                .join(" && "),
+        );
+
+        print::bullet(format!(
+            "Versions from {} in {image_name}",
+            inner_ruby.display()
+        ));
+        markdown.push_str(&format!("### Versions in {image_name}\n\n"));
+        let facts = match print::sub_stream_cmd(cmd) {
+            Ok(output) => {
+                let stdout = output.stdout_lossy();
+                markdown.push_str(&format!("{stdout}\n"));
+                Some(RuntimeFacts::parse(&stdout))
+            }
+            Err(error) => {
+                markdown.push_str("❌ Failed, see the job log\n\n");
+                failures.push(format!("Ruby failed to run in {image_name}: {error}"));
+                None
+            }
+        };
 
-    print::bullet("Versions");
-    let output = print::sub_stream_cmd(cmd)?;
+        let mut cmd = docker_run();
+        cmd.args([
+            "--volume",
+            &format!(
+                "{}:{}:ro",
+                smoke_tests_dir.display(),
+                smoke_test::SUITE_MOUNT
+            ),
+        ]);
+        cmd.args(["--workdir", "/"]);
+        cmd.args(["--env", &ruby_path]);
+        cmd.arg(&image_name);
+        cmd.args(["bash", "-c", smoke_test::RUNNER_SCRIPT]);
+
+        print::bullet(format!("Smoke tests in {image_name}"));
+        let results =
+            smoke_test::parse_results(&smoke_test_names, &print::sub_time_cmd(cmd)?.stdout_lossy());
+        for result in &results {
+            print::sub_bullet(format!(
+                "{} {} ({}ms)",
+                result.outcome,
+                result.name,
+                result.duration.as_millis()
+            ));
+        }
+        markdown.push_str(&format!(
+            "{}\n",
+            smoke_test::to_markdown(&results, &image_name)
+        ));
+        let failed = results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Failed { .. }))
+            .map(|result| format!("  - {} {}:\n{}", result.outcome, result.name, result.output))
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            failures.push(format!(
+                "Smoke tests failed in {image_name}:\n{}",
+                failed.join("\n")
+            ));
+        }
+
+        check.images.push(ImageResult {
+            image: image_name,
+            facts,
+            tests: results.iter().map(TestResult::from).collect(),
+        });
+    }
+
+    // Linkage is checked in the run image, the build image has libraries dynos don't
+    let run_image = base_image.docker_image(ImageFlavor::Run);
+    let mut cmd = docker_run();
+    cmd.args(["--workdir", &inner_ruby.display().to_string()]);
+    cmd.arg(&run_image);
+    cmd.args(["bash", "-c", LDD_SCRIPT]);
+
+    print::bullet(format!("Linkage in {run_image}"));
+    let linkage = LinkageReport::parse(&print::sub_time_cmd(cmd)?.stdout_lossy());
+    let problems = linkage.problems(&inner_ruby);
+    for problem in &problems {
+        print::sub_bullet(problem.to_string());
+    }
+    if problems.is_empty() {
+        print::sub_bullet(format!(
+            "{} files, all libraries found",
+            linkage.files.len()
+        ));
+    }
+    markdown.push_str(&linkage.to_markdown(&inner_ruby, &run_image));
+
+    if !leaks.is_empty() {
+        failures.push(format!(
+            "Ruby {version} is not relocatable, these files reference {BUILD_PREFIX}:\n{}",
+            bullet_list(&leaks)
+        ));
+    }
+    let missing = problems
+        .iter()
+        .filter(|problem| matches!(problem, LinkageProblem::NotFound { .. }))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        failures.push(format!(
+            "Ruby {version} needs libraries {run_image} doesn't have:\n{}",
+            bullet_list(&missing)
+        ));
+    }
 
     print::all_done(&Some(start));
     print::plain("");
 
-    // Print results to STDOUT for github summary
-    println!("## Ruby {version} linux/{arch} for {base_image}");
-    println!();
-    println!("{}", output.stdout_lossy());
+    // Print results to STDOUT for the github summary, or for other tooling with `--format json`
+    match format {
+        OutputFormat::Markdown => println!("{markdown}"),
+        OutputFormat::Json => println!(
+            "{}",
+            check.finish(failures.clone(), start.elapsed()).to_json()
+        ),
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n\n").into())
+    }
+}
 
-    Ok(())
+fn bullet_list(items: &[impl ToString]) -> String {
+    items
+        .iter()
+        .map(|item| format!("  - {}", item.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 fn main() {
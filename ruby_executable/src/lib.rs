@@ -1 +1,4 @@
 // See `bin/*.rs` for scripts
+
+pub mod release_check;
+pub mod smoke_test;
@@ -0,0 +1,374 @@
+//! Checking ruby-lang.org for Ruby releases that aren't on S3 yet
+//!
+//! See [`shared::release_check`] for the pipeline this plugs into.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeDelta, Utc};
+use reqwest::{Client, Url};
+use shared::http::{self, RequestClass};
+use shared::maybe_err::ResultVec;
+use shared::release_check::{BoxError, CheckError, ReleaseEngine, Releases};
+use shared::{Compression, RubyDownloadVersion, ruby_tar_file_name, settle};
+use yaml_rust2::{ScanError, Yaml, YamlLoader};
+
+pub static RELEASES_URL: std::sync::LazyLock<Url> = std::sync::LazyLock::new(|| {
+    Url::parse("https://raw.githubusercontent.com/ruby/www.ruby-lang.org/master/_data/releases.yml")
+        .expect("valid releases URL constant")
+});
+
+/// Ruby releases from ruby-lang.org at or above `minimum_version` that have settled
+#[derive(Debug, Clone)]
+pub struct RubyEngine {
+    pub minimum_version: RubyDownloadVersion,
+    /// See [`shared::settle`]. Release dates have day granularity and a release can go out any
+    /// time that day, so the window is measured from the end of the release date (UTC).
+    pub settle_window: TimeDelta,
+    /// Compression of the artifacts to look for, see [`Compression::extension`]
+    pub compression: Compression,
+}
+
+impl ReleaseEngine for RubyEngine {
+    type Version = RubyDownloadVersion;
+
+    fn name(&self) -> &'static str {
+        "Ruby"
+    }
+
+    async fn releases(&self) -> Releases<RubyDownloadVersion> {
+        let mut releases = Releases::default();
+        let listed = match fetch_ruby_lang_body(&RELEASES_URL).await {
+            Ok(body) => {
+                let mut errors: Vec<RubyLangEntryError> = Vec::new();
+                let listed = ruby_lang_versions(body).unwrap_drain_errs(&mut errors);
+                releases.errors.extend(
+                    errors
+                        .into_iter()
+                        .map(|error| CheckError::new(format!("parsing {}", *RELEASES_URL), error)),
+                );
+                listed
+            }
+            Err(error) => {
+                releases.errors.push(CheckError::new(
+                    format!("fetching releases from {}", *RELEASES_URL),
+                    error,
+                ));
+                Vec::new()
+            }
+        };
+
+        let now = Utc::now();
+        for release in listed {
+            if !version_gte(&release.version, &self.minimum_version) {
+                continue;
+            }
+            match release.settle_remaining(now, self.settle_window) {
+                None => releases.versions.push(release.version),
+                Some(remaining) => releases.notes.push(format!(
+                    "{}: skipping, released recently, settles in {}",
+                    release.version,
+                    settle::format_remaining(remaining)
+                )),
+            }
+        }
+        releases
+    }
+
+    async fn artifact_name(&self, version: &RubyDownloadVersion) -> Result<String, BoxError> {
+        Ok(ruby_tar_file_name(version, self.compression))
+    }
+}
+
+async fn get_body(client: &Client, url: Url) -> Result<String, reqwest::Error> {
+    client
+        .get(url)
+        .timeout(RequestClass::Api.timeout())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+async fn fetch_ruby_lang_body(url: &Url) -> Result<String, shared::Error> {
+    let client = http::client().map_err(shared::Error::HttpClient)?;
+
+    shared::with_retries(|| get_body(client, url.clone()))
+        .await
+        .map_err(shared::Error::FailedRequest)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FlatYamlError {
+    #[error("Cannot parse yaml due to error {1} from input:\n{0}")]
+    NotYaml(String, ScanError),
+    #[error("Expected first yaml element to be a vec but it was not: {1:?} from input:\n{0}")]
+    FirstNotVec(String, Vec<Yaml>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RubyLangEntryError {
+    #[error(transparent)]
+    DocError(#[from] FlatYamlError),
+
+    #[error("expected yaml to have a `version` field but it did not: {0:?}")]
+    MissingVersion(Yaml),
+
+    #[error(transparent)]
+    CannotParse(#[from] shared::Error),
+
+    #[error("expected `date` of {version} to be YYYY-MM-DD but it was {date:?}: {error}")]
+    InvalidDate {
+        version: RubyDownloadVersion,
+        date: String,
+        error: chrono::ParseError,
+    },
+}
+
+/// A release listed on ruby-lang.org
+#[derive(Debug, Clone)]
+pub struct RubyLangRelease {
+    pub version: RubyDownloadVersion,
+    /// The release date, entries without one are treated as long settled
+    pub date: Option<NaiveDate>,
+}
+
+impl RubyLangRelease {
+    fn from_yaml(entry: &Yaml) -> Result<Self, RubyLangEntryError> {
+        let version = entry["version"]
+            .as_str()
+            .ok_or_else(|| RubyLangEntryError::MissingVersion(entry.clone()))
+            .and_then(|v| RubyDownloadVersion::new(v).map_err(RubyLangEntryError::CannotParse))?;
+        let date = entry["date"]
+            .as_str()
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|error| {
+                    RubyLangEntryError::InvalidDate {
+                        version: version.clone(),
+                        date: date.to_string(),
+                        error,
+                    }
+                })
+            })
+            .transpose()?;
+
+        Ok(RubyLangRelease { version, date })
+    }
+
+    /// How much longer the release has to wait before it has been public for `window`
+    ///
+    /// Without a time of day the release is assumed to have gone out at the last moment of its
+    /// date, so the window is never cut short by a release published late in the day.
+    pub fn settle_remaining(&self, now: DateTime<Utc>, window: TimeDelta) -> Option<TimeDelta> {
+        let end_of_day = self.date?.succ_opt()?.and_time(NaiveTime::MIN).and_utc();
+        settle::settle_remaining(end_of_day, now, window)
+    }
+}
+
+/// Parse output from <https://raw.githubusercontent.com/ruby/www.ruby-lang.org/master/_data/releases.yml>
+pub fn parse_flat_yaml(body: String) -> Result<Vec<Yaml>, FlatYamlError> {
+    YamlLoader::load_from_str(&body)
+        .map_err(|error| FlatYamlError::NotYaml(body.clone(), error))
+        .and_then(|docs| {
+            docs.first()
+                .and_then(|doc| doc.as_vec())
+                .cloned()
+                .ok_or(FlatYamlError::FirstNotVec(body.clone(), docs.clone()))
+        })
+}
+
+/// Parses output from Ruby Lang into Ruby releases
+///
+/// Fault tolerant parse result of <https://raw.githubusercontent.com/ruby/www.ruby-lang.org/master/_data/releases.yml>
+pub fn ruby_lang_versions(body: String) -> ResultVec<RubyLangRelease, RubyLangEntryError> {
+    match parse_flat_yaml(body) {
+        Ok(entries) => entries.iter().map(RubyLangRelease::from_yaml).collect(),
+        Err(error) => vec![Err(error.into())],
+    }
+    .into()
+}
+
+pub fn version_gte(version: &RubyDownloadVersion, minimum: &RubyDownloadVersion) -> bool {
+    let version_tuple = (version.major, version.minor, version.patch);
+    let minimum_tuple = (minimum.major, minimum.minor, minimum.patch);
+    version_tuple >= minimum_tuple
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::assert_matches;
+
+    #[test]
+    fn ruby_lang_parsing_returns_partial_result_on_parse_failure() {
+        let body = indoc::indoc! {"
+            - version: 4.0.5
+            - version: 4.doesnotparse.5
+        "}
+        .to_string();
+
+        let mut errors = Vec::new();
+        let versions = ruby_lang_versions(body).unwrap_drain_errs(&mut errors);
+        assert_eq!(
+            vec![String::from("4.0.5")],
+            versions
+                .iter()
+                .map(|r| r.version.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(1, errors.len());
+        assert_matches!(
+            errors.into_iter().next().unwrap(),
+            RubyLangEntryError::CannotParse(_)
+        );
+    }
+
+    #[test]
+    fn parse_flat_yaml_errors_on_unparseable_yaml() {
+        let body = String::from("cannot_parse: 'unterminated_string");
+        assert_matches!(parse_flat_yaml(body), Err(FlatYamlError::NotYaml(_, _)));
+    }
+
+    #[test]
+    fn parse_flat_yaml_errors_when_top_level_not_vec() {
+        let body = String::from("version: 4.0.5");
+        assert_matches!(parse_flat_yaml(body), Err(FlatYamlError::FirstNotVec(_, _)));
+    }
+
+    #[test]
+    fn ruby_lang_versions_errors_on_missing_version_field() {
+        let body = indoc::indoc! {"
+            - name: ruby
+            - version: 4.0.5
+        "}
+        .to_string();
+
+        let mut errors = Vec::new();
+        let versions = ruby_lang_versions(body).unwrap_drain_errs(&mut errors);
+        assert_eq!(
+            vec![String::from("4.0.5")],
+            versions
+                .iter()
+                .map(|r| r.version.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(1, errors.len());
+        assert_matches!(
+            errors.into_iter().next().unwrap(),
+            RubyLangEntryError::MissingVersion(_)
+        );
+    }
+
+    #[test]
+    fn ruby_lang_versions_parses_dates() {
+        let body = indoc::indoc! {"
+            - version: 3.4.5
+              date: 2025-07-16
+            - version: 3.4.4
+              date: July 1st
+            - version: 1.8.7
+        "}
+        .to_string();
+
+        let mut errors = Vec::new();
+        let releases = ruby_lang_versions(body).unwrap_drain_errs(&mut errors);
+        assert_eq!(
+            vec![
+                (String::from("3.4.5"), NaiveDate::from_ymd_opt(2025, 7, 16)),
+                (String::from("1.8.7"), None)
+            ],
+            releases
+                .iter()
+                .map(|r| (r.version.to_string(), r.date))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(1, errors.len());
+        assert_matches!(
+            errors.into_iter().next().unwrap(),
+            RubyLangEntryError::InvalidDate { .. }
+        );
+    }
+
+    #[test]
+    fn ruby_lang_release_settles_from_end_of_day_utc() {
+        let release = RubyLangRelease {
+            version: RubyDownloadVersion::new("3.4.5").unwrap(),
+            date: NaiveDate::from_ymd_opt(2025, 7, 16),
+        };
+        let midnight = DateTime::parse_from_rfc3339("2025-07-17T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let window = TimeDelta::hours(1);
+
+        assert_eq!(
+            release.settle_remaining(midnight + TimeDelta::minutes(10), window),
+            Some(TimeDelta::minutes(50))
+        );
+        assert_eq!(
+            release.settle_remaining(midnight + TimeDelta::hours(1), window),
+            None
+        );
+
+        let undated = RubyLangRelease {
+            date: None,
+            ..release
+        };
+        assert_eq!(undated.settle_remaining(midnight, window), None);
+    }
+
+    #[test]
+    fn ruby_lang_release_published_late_in_the_day_is_not_settled() {
+        let release = RubyLangRelease {
+            version: RubyDownloadVersion::new("3.4.5").unwrap(),
+            date: NaiveDate::from_ymd_opt(2025, 7, 16),
+        };
+        // Published at 18:00 UTC, checked 10 minutes later
+        let now = DateTime::parse_from_rfc3339("2025-07-16T18:10:00Z")
+            .unwrap()
+            .to_utc();
+
+        assert_eq!(
+            release.settle_remaining(now, TimeDelta::hours(1)),
+            Some(TimeDelta::hours(6) + TimeDelta::minutes(50))
+        );
+    }
+
+    #[test]
+    fn test_version_gte() {
+        let min = RubyDownloadVersion::new("3.2.0").unwrap();
+        assert!(version_gte(
+            &RubyDownloadVersion::new("3.2.0").unwrap(),
+            &min
+        ));
+        assert!(version_gte(
+            &RubyDownloadVersion::new("3.3.7").unwrap(),
+            &min
+        ));
+        assert!(version_gte(
+            &RubyDownloadVersion::new("4.0.0").unwrap(),
+            &min
+        ));
+        assert!(!version_gte(
+            &RubyDownloadVersion::new("3.1.9").unwrap(),
+            &min
+        ));
+        assert!(!version_gte(
+            &RubyDownloadVersion::new("2.7.8").unwrap(),
+            &min
+        ));
+    }
+
+    #[test]
+    fn test_version_gte_prerelease() {
+        let min = RubyDownloadVersion::new("3.4.0").unwrap();
+        assert!(version_gte(
+            &RubyDownloadVersion::new("3.4.0-preview1").unwrap(),
+            &min
+        ));
+        assert!(!version_gte(
+            &RubyDownloadVersion::new("3.3.9").unwrap(),
+            &min
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+//! Smoke tests run against a built Ruby by `ruby_check`
+//!
+//! Every `*.rb` file in the suite directory (`smoke_tests/` by default) is one test, named after
+//! the file. A test passes when it exits 0, is skipped when it exits [`SKIP_EXIT_CODE`] (for
+//! something the Ruby version doesn't have), and fails otherwise. [`RUNNER_SCRIPT`] runs them all
+//! inside the container and [`parse_results`] reads its output back.
+
+use fs_err as fs;
+use shared::check_result::{TestResult, TestStatus};
+use shared::{source_dir, summary::Table};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Exit code for a skipped test, the automake convention
+pub const SKIP_EXIT_CODE: i32 = 77;
+
+/// Where the suite is mounted in the container
+pub const SUITE_MOUNT: &str = "/tmp/smoke_tests";
+
+/// Runs each test in [`SUITE_MOUNT`] with the `ruby` on the `PATH`
+///
+/// Prints `==> <name>`, the test's output, then `<== <name> <exit code> <milliseconds>`.
+pub const RUNNER_SCRIPT: &str = r#"for test in /tmp/smoke_tests/*.rb; do
+  name=$(basename "$test" .rb)
+  echo "==> $name"
+  start=$(date +%s%N)
+  status=0
+  output=$(timeout 60 ruby "$test" 2>&1) || status=$?
+  [ -n "$output" ] && printf '%s\n' "$output"
+  echo "<== $name $status $(( ($(date +%s%N) - start) / 1000000 ))"
+done"#;
+
+pub fn default_suite_dir() -> PathBuf {
+    source_dir().join("smoke_tests")
+}
+
+/// Names of the tests in `dir`, sorted
+pub fn test_names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|extension| extension == "rb"))
+        .filter_map(|path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Skipped,
+    /// `None` when the test never reported back
+    Failed {
+        exit_code: Option<i32>,
+    },
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed => f.write_str("✅ passed"),
+            Outcome::Skipped => f.write_str("⏭️ skipped"),
+            Outcome::Failed {
+                exit_code: Some(code),
+            } => write!(f, "❌ failed (exit {code})"),
+            Outcome::Failed { exit_code: None } => f.write_str("❌ did not finish"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmokeTestResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    /// Combined stdout and stderr
+    pub output: String,
+}
+
+impl From<&SmokeTestResult> for TestResult {
+    fn from(result: &SmokeTestResult) -> Self {
+        TestResult {
+            name: result.name.clone(),
+            status: match result.outcome {
+                Outcome::Passed => TestStatus::Passed,
+                Outcome::Skipped => TestStatus::Skipped,
+                Outcome::Failed { .. } => TestStatus::Failed,
+            },
+            duration_secs: result.duration.as_secs_f64(),
+            output: result.output.clone(),
+        }
+    }
+}
+
+/// Read [`RUNNER_SCRIPT`] output, tests in `names` that never reported back have failed
+pub fn parse_results(names: &[String], output: &str) -> Vec<SmokeTestResult> {
+    let mut results = Vec::<SmokeTestResult>::new();
+    let mut current: Option<SmokeTestResult> = None;
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("==> ") {
+            results.extend(current.take());
+            current = Some(SmokeTestResult {
+                name: name.to_string(),
+                outcome: Outcome::Failed { exit_code: None },
+                duration: Duration::ZERO,
+                output: String::new(),
+            });
+        } else if let Some(rest) = line.strip_prefix("<== ") {
+            // `<name> <exit code> <milliseconds>`
+            let mut parts = rest.rsplitn(3, ' ');
+            let millis = parts.next().and_then(|millis| millis.parse().ok());
+            let exit_code = parts.next().and_then(|code| code.parse::<i32>().ok());
+            if let Some(result) = current.as_mut() {
+                result.outcome = match exit_code {
+                    Some(0) => Outcome::Passed,
+                    Some(SKIP_EXIT_CODE) => Outcome::Skipped,
+                    _ => Outcome::Failed { exit_code },
+                };
+                result.duration = Duration::from_millis(millis.unwrap_or_default());
+            }
+            results.extend(current.take());
+        } else if let Some(result) = current.as_mut() {
+            result.output.push_str(line);
+            result.output.push('\n');
+        }
+    }
+    results.extend(current);
+
+    for name in names {
+        if !results.iter().any(|result| &result.name == name) {
+            results.push(SmokeTestResult {
+                name: name.clone(),
+                outcome: Outcome::Failed { exit_code: None },
+                duration: Duration::ZERO,
+                output: String::new(),
+            });
+        }
+    }
+    results
+}
+
+pub fn to_markdown(results: &[SmokeTestResult], image: &str) -> String {
+    let mut table = Table::new(["Test", "Result", "Time (ms)", "Output"].map(String::from));
+    for result in results {
+        table.row([
+            result.name.clone(),
+            result.outcome.to_string(),
+            result.duration.as_millis().to_string(),
+            result.output.lines().last().unwrap_or_default().to_string(),
+        ]);
+    }
+    format!("### Smoke tests in {image}\n\n{table}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_runner_output() {
+        let output = indoc::indoc! {"
+            ==> bigdecimal
+            bigdecimal 3.1.8
+            <== bigdecimal 0 41
+            ==> openssl
+            openssl.rb:45: Expected ping back (RuntimeError)
+            <== openssl 1 250
+            ==> yjit
+            <== yjit 77 30
+            ==> zlib
+        "};
+        let names = ["bigdecimal", "openssl", "psych", "yjit", "zlib"].map(String::from);
+
+        let results = parse_results(&names, output);
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (
+                    result.name.as_str(),
+                    result.outcome,
+                    result.duration.as_millis()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bigdecimal", Outcome::Passed, 41),
+                ("openssl", Outcome::Failed { exit_code: Some(1) }, 250),
+                ("yjit", Outcome::Skipped, 30),
+                ("zlib", Outcome::Failed { exit_code: None }, 0),
+                ("psych", Outcome::Failed { exit_code: None }, 0),
+            ]
+        );
+        assert_eq!(
+            results[1].output,
+            "openssl.rb:45: Expected ping back (RuntimeError)\n"
+        );
+        assert!(
+            to_markdown(&results, "heroku/heroku:24")
+                .contains("| bigdecimal | ✅ passed | 41 | bigdecimal 3.1.8 |")
+        );
+    }
+
+    #[test]
+    fn default_suite_has_tests() {
+        let names = test_names(&default_suite_dir()).unwrap();
+        for expected in ["openssl", "psych", "yjit", "zlib"] {
+            assert!(names.iter().any(|name| name == expected), "{names:?}");
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! Policy checks for built tarballs
+//!
+//! [`audit_tarball`] reads an archive without extracting it and reports anything we don't want
+//! to ship: symlinks that are absolute or point outside the archive, setuid/setgid or world
+//! writable entries, Windows leftovers in `bin/`, scripts with an absolute Ruby shebang that
+//! [`crate::update_shebangs_in_dir`] should have rewritten, and files still mentioning the
+//! [`BUILD_PREFIX`] Ruby is configured with in `make_ruby.sh`.
+//!
+//! Findings are either errors, which [`AuditReport::into_result`] turns into an
+//! [`AuditError::Violations`], or warnings that are only reported.
+
+use crate::summary::Table;
+use crate::{Compression, relocatable, update_shebang};
+use fs_err as fs;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// The `--prefix` used by `make_ruby.sh`, the tarball is meant to work from any directory
+pub const BUILD_PREFIX: &str = "/tmp/compiled";
+
+/// Extensions that only make sense on Windows, `jruby_build` removes them from `bin/`
+const WINDOWS_EXTENSIONS: [&str; 3] = ["bat", "dll", "exe"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("Cannot audit {path}, expected a `.tgz`, `.tar.gz`, `.tar.zst` or `.tar.xz` extension")]
+    UnknownCompression { path: PathBuf },
+
+    #[error("Cannot read tarball {path}: {source}")]
+    CannotRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Tarball {path} violates the artifact policy:\n{}", list(findings))]
+    Violations {
+        path: PathBuf,
+        findings: Vec<Finding>,
+    },
+}
+
+fn list(findings: &[Finding]) -> String {
+    findings
+        .iter()
+        .map(|finding| format!("  - {finding}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Fails the build
+    Error,
+    /// Reported, but the tarball still ships
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    AbsoluteSymlink { target: String },
+    EscapingSymlink { target: String },
+    Setuid,
+    Setgid,
+    WorldWritable,
+    WindowsLeftover,
+    AbsoluteRubyShebang { shebang: String },
+    HardcodedPrefix,
+}
+
+impl FindingKind {
+    /// Mentions of [`BUILD_PREFIX`] are warnings: Ruby is configured with
+    /// `--enable-load-relative` so it finds its files relative to the binary, but `rbconfig.rb`,
+    /// pkg-config files and headers still record the prefix.
+    pub fn severity(&self) -> Severity {
+        match self {
+            FindingKind::HardcodedPrefix => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingKind::AbsoluteSymlink { target } => write!(f, "absolute symlink to {target}"),
+            FindingKind::EscapingSymlink { target } => {
+                write!(f, "symlink to {target} points outside the archive")
+            }
+            FindingKind::Setuid => f.write_str("setuid bit set"),
+            FindingKind::Setgid => f.write_str("setgid bit set"),
+            FindingKind::WorldWritable => f.write_str("world writable"),
+            FindingKind::WindowsLeftover => f.write_str("Windows only file"),
+            FindingKind::AbsoluteRubyShebang { shebang } => {
+                write!(f, "absolute Ruby shebang {shebang}")
+            }
+            FindingKind::HardcodedPrefix => write!(f, "contains build prefix {BUILD_PREFIX}"),
+        }
+    }
+}
+
+/// A policy violation at `path` inside the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub path: String,
+    pub kind: FindingKind,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditReport {
+    pub path: PathBuf,
+    pub findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.with_severity(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.with_severity(Severity::Warning)
+    }
+
+    fn with_severity(&self, severity: Severity) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(move |finding| finding.kind.severity() == severity)
+    }
+
+    /// Errors when any finding has [`Severity::Error`], returning the warnings otherwise
+    pub fn into_result(self) -> Result<Vec<Finding>, AuditError> {
+        let (errors, warnings) = self
+            .findings
+            .into_iter()
+            .partition::<Vec<_>, _>(|finding| finding.kind.severity() == Severity::Error);
+        if errors.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(AuditError::Violations {
+                path: self.path,
+                findings: errors,
+            })
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        if self.findings.is_empty() {
+            return format!("## Audit of {name}\n\n✅ No findings\n");
+        }
+        let mut table = Table::new(["Severity", "Path", "Finding"].map(String::from));
+        for finding in &self.findings {
+            table.row([
+                match finding.kind.severity() {
+                    Severity::Error => "❌ error",
+                    Severity::Warning => "⚠️ warning",
+                }
+                .to_string(),
+                format!("`{}`", finding.path),
+                finding.kind.to_string(),
+            ]);
+        }
+        format!("## Audit of {name}\n\n{table}")
+    }
+}
+
+/// Check every entry of the tarball at `path` against the policy in the module docs
+pub fn audit_tarball(path: &Path) -> Result<AuditReport, AuditError> {
+    let (_, compression) =
+        Compression::from_file_name(&path.file_name().unwrap_or_default().to_string_lossy())
+            .ok_or_else(|| AuditError::UnknownCompression {
+                path: path.to_path_buf(),
+            })?;
+    let findings = fs::File::open(path)
+        .and_then(|file| compression.decoder(file))
+        .and_then(audit_entries)
+        .map_err(|source| AuditError::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+    Ok(AuditReport {
+        path: path.to_path_buf(),
+        findings,
+    })
+}
+
+fn audit_entries(tar: impl Read) -> std::io::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut archive = tar::Archive::new(tar);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let name = path
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect::<PathBuf>();
+        let mut found = |kind| {
+            findings.push(Finding {
+                path: name.display().to_string(),
+                kind,
+            })
+        };
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() {
+            if let Some(target) = entry.link_name()? {
+                let target_name = target.display().to_string();
+                if target.is_absolute() {
+                    found(FindingKind::AbsoluteSymlink {
+                        target: target_name,
+                    });
+                } else if escapes(&name, &target) {
+                    found(FindingKind::EscapingSymlink {
+                        target: target_name,
+                    });
+                }
+            }
+            continue;
+        }
+
+        let mode = entry.header().mode()?;
+        if mode & 0o4000 != 0 {
+            found(FindingKind::Setuid);
+        }
+        if mode & 0o2000 != 0 {
+            found(FindingKind::Setgid);
+        }
+        if mode & 0o002 != 0 {
+            found(FindingKind::WorldWritable);
+        }
+
+        if !entry_type.is_file() {
+            continue;
+        }
+        if name.starts_with("bin")
+            && path.extension().is_some_and(|extension| {
+                WINDOWS_EXTENSIONS.contains(&&*extension.to_string_lossy())
+            })
+        {
+            found(FindingKind::WindowsLeftover);
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        if relocatable::is_script(&name) {
+            let text = String::from_utf8_lossy(&contents);
+            let shebang = text.lines().next().unwrap_or_default();
+            if update_shebang(shebang.to_string()).is_some() {
+                found(FindingKind::AbsoluteRubyShebang {
+                    shebang: shebang.to_string(),
+                });
+            }
+        }
+        if contains(&contents, BUILD_PREFIX.as_bytes()) {
+            found(FindingKind::HardcodedPrefix);
+        }
+    }
+    Ok(findings)
+}
+
+/// True when following `target` from the directory holding `link` leaves the archive root
+fn escapes(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::ParentDir => {
+                let Some(parent) = depth.checked_sub(1) else {
+                    return true;
+                };
+                depth = parent;
+            }
+            Component::Normal(_) => depth += 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_tgz(dir: &Path, build: impl FnOnce(&mut tar::Builder<Vec<u8>>)) -> PathBuf {
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder);
+        let mut encoder = Compression::Gzip.encoder(Vec::new()).unwrap();
+        std::io::Write::write_all(&mut encoder, &builder.into_inner().unwrap()).unwrap();
+        let path = dir.join("ruby-3.4.1.tgz");
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        path
+    }
+
+    fn file(builder: &mut tar::Builder<Vec<u8>>, path: &str, mode: u32, contents: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+
+    fn symlink(builder: &mut tar::Builder<Vec<u8>>, path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+
+    #[test]
+    fn clean_tarball_has_no_findings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture_tgz(dir.path(), |builder| {
+            file(builder, "./bin/ruby", 0o755, b"\x7fELF");
+            file(builder, "./bin/rake", 0o755, b"#!/usr/bin/env ruby\n");
+            file(
+                builder,
+                "./lib/ruby/3.4.0/un.rb",
+                0o644,
+                b"#!/usr/local/bin/ruby\n",
+            );
+            file(builder, "./lib/libruby.so.3.4.1", 0o755, b"\x7fELF");
+            symlink(builder, "./lib/libruby.so", "libruby.so.3.4.1");
+            symlink(builder, "./lib/ruby/3.4.0/up", "../../../bin/ruby");
+        });
+
+        let report = audit_tarball(&path).unwrap();
+        assert_eq!(report.findings, Vec::new());
+        assert!(report.to_markdown().contains("No findings"));
+        assert_eq!(report.into_result().unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn reports_policy_violations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = fixture_tgz(dir.path(), |builder| {
+            symlink(builder, "bin/abs", "/usr/bin/ruby");
+            symlink(builder, "bin/out", "../../etc/passwd");
+            file(builder, "bin/suid", 0o4755, b"");
+            file(builder, "bin/sgid", 0o2755, b"");
+            file(builder, "bin/open", 0o666, b"");
+            file(builder, "bin/jruby.bat", 0o644, b"");
+            file(builder, "lib/jni/x86_64-Windows/jffi.dll", 0o644, b"");
+            file(builder, "bin/rake", 0o755, b"#!/usr/local/bin/ruby\n");
+            file(
+                builder,
+                "lib/rbconfig.rb",
+                0o644,
+                b"prefix = \"/tmp/compiled\"",
+            );
+        });
+
+        let report = audit_tarball(&path).unwrap();
+        let found = |path: &str, kind: FindingKind| Finding {
+            path: path.to_string(),
+            kind,
+        };
+        assert_eq!(
+            report.findings,
+            vec![
+                found(
+                    "bin/abs",
+                    FindingKind::AbsoluteSymlink {
+                        target: "/usr/bin/ruby".to_string()
+                    }
+                ),
+                found(
+                    "bin/out",
+                    FindingKind::EscapingSymlink {
+                        target: "../../etc/passwd".to_string()
+                    }
+                ),
+                found("bin/suid", FindingKind::Setuid),
+                found("bin/sgid", FindingKind::Setgid),
+                found("bin/open", FindingKind::WorldWritable),
+                found("bin/jruby.bat", FindingKind::WindowsLeftover),
+                found(
+                    "bin/rake",
+                    FindingKind::AbsoluteRubyShebang {
+                        shebang: "#!/usr/local/bin/ruby".to_string()
+                    }
+                ),
+                found("lib/rbconfig.rb", FindingKind::HardcodedPrefix),
+            ]
+        );
+        assert_eq!(report.warnings().count(), 1);
+        assert_eq!(report.errors().count(), 7);
+        assert!(
+            report.to_markdown().contains(
+                "| ⚠️ warning | `lib/rbconfig.rb` | contains build prefix /tmp/compiled |"
+            )
+        );
+
+        let error = report.into_result().unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("  - bin/abs: absolute symlink to /usr/bin/ruby"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn symlink_escapes() {
+        assert!(!escapes(
+            Path::new("lib/libruby.so"),
+            Path::new("libruby.so.3")
+        ));
+        assert!(!escapes(Path::new("lib/a/b"), Path::new("../../bin/ruby")));
+        assert!(escapes(
+            Path::new("lib/a/b"),
+            Path::new("../../../bin/ruby")
+        ));
+        assert!(escapes(Path::new("ruby"), Path::new("../ruby")));
+        assert!(!escapes(
+            Path::new("bin/ruby"),
+            Path::new("x/../../bin/jruby")
+        ));
+    }
+}
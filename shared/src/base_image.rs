@@ -60,6 +60,34 @@ impl BaseImage {
     }
 }
 
+/// Which of a base image's Docker images to use
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFlavor {
+    /// What apps run on, without compilers or `-dev` packages
+    Run,
+    /// The run image plus build tooling and headers
+    Build,
+}
+
+impl Display for ImageFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ImageFlavor::Run => "run",
+            ImageFlavor::Build => "build",
+        })
+    }
+}
+
+impl BaseImage {
+    /// Docker image name, e.g. `heroku/heroku:24-build`
+    pub fn docker_image(&self, flavor: ImageFlavor) -> String {
+        match flavor {
+            ImageFlavor::Run => format!("heroku/heroku:{}", self.distro_number),
+            ImageFlavor::Build => format!("heroku/heroku:{}-build", self.distro_number),
+        }
+    }
+}
+
 impl FromStr for BaseImage {
     type Err = BaseImageError;
 
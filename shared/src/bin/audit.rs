@@ -0,0 +1,61 @@
+//! Check built tarballs against the artifact policy
+//!
+//! ```term
+//! $ cargo run --bin audit -- output/heroku-24/amd64/ruby-3.4.1.tgz
+//! ```
+
+use bullet_stream::global::print;
+use clap::Parser;
+use indoc::formatdoc;
+use shared::audit;
+use shared::summary::append_markdown;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Tarballs to audit
+    #[arg(required = true)]
+    tarballs: Vec<PathBuf>,
+
+    /// Append a Markdown table of the findings to this file, e.g. `$GITHUB_STEP_SUMMARY`
+    #[arg(long = "summary-markdown")]
+    summary_markdown: Option<PathBuf>,
+}
+
+fn call(args: &Args) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    print::h2("Auditing tarballs");
+    let mut failed = Vec::new();
+    for tarball in &args.tarballs {
+        print::bullet(format!("Auditing {}", tarball.display()));
+        let report = audit::audit_tarball(tarball)?;
+        append_markdown(args.summary_markdown.as_deref(), &report.to_markdown())?;
+        for finding in report.findings.iter() {
+            print::sub_bullet(format!("{:?}: {finding}", finding.kind.severity()));
+        }
+        if let Err(error) = report.into_result() {
+            failed.push(error.to_string());
+        }
+    }
+    print::all_done(&Some(start));
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed.join("\n\n").into())
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(error) = call(&args) {
+        print::error(formatdoc! {"
+            ❌ Command failed ❌
+
+            {error}
+        "});
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Compare gzip, zstd and xz for an existing tarball
+//!
+//! Extracts the tarball, re-packs it reproducibly with every supported compression, and
+//! reports the size and time each one takes.
+//!
+//! ```term
+//! $ cargo run --bin compression_report -- output/heroku-24/amd64/ruby-3.4.1.tgz
+//! ```
+
+use bullet_stream::global::print;
+use clap::Parser;
+use fs_err as fs;
+use indoc::formatdoc;
+use shared::{
+    Compression, TarDownloadPath, TarMode, summary::Table, summary::append_markdown,
+    tar_dir_to_file, untar_to_dir,
+};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Tarball to re-compress, in any supported format
+    tarball: PathBuf,
+
+    /// Append the report to this file, e.g. `$GITHUB_STEP_SUMMARY`
+    #[arg(long = "summary-markdown")]
+    summary_markdown: Option<PathBuf>,
+}
+
+fn call(args: &Args) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let name = args
+        .tarball
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    print::h2(format!("Compression report for {name}"));
+
+    let temp = tempfile::tempdir()?;
+    let extracted = temp.path().join("extracted");
+    print::bullet(format!("Extracting {}", args.tarball.display()));
+    untar_to_dir(&TarDownloadPath(args.tarball.clone()), &extracted)?;
+
+    let mut results = Vec::new();
+    for compression in Compression::ALL {
+        let path = temp.path().join(format!("out{}", compression.extension()));
+        let timer = print::sub_start_timer(format!("Compress with {compression}"));
+        let started = Instant::now();
+        tar_dir_to_file(
+            &extracted,
+            &fs::File::create(&path)?,
+            TarMode::Reproducible { mtime: 0 },
+            compression,
+        )?;
+        let elapsed = started.elapsed();
+        timer.done();
+        results.push((compression, fs::metadata(&path)?.len(), elapsed));
+    }
+
+    let gzip_size = results
+        .iter()
+        .find(|(compression, _, _)| *compression == Compression::Gzip)
+        .map(|(_, size, _)| *size)
+        .unwrap_or_default();
+    let mut table =
+        Table::new(["Compression", "Size (MiB)", "vs gzip", "Time (s)"].map(String::from));
+    for (compression, size, elapsed) in &results {
+        table.row([
+            format!("{compression} (`{}`)", compression.extension()),
+            format!("{:.2}", *size as f64 / 1024.0 / 1024.0),
+            format!("{:.0}%", *size as f64 / gzip_size.max(1) as f64 * 100.0),
+            format!("{:.1}", elapsed.as_secs_f64()),
+        ]);
+    }
+    let markdown = format!("## Compression report for {name}\n\n{table}");
+
+    print::all_done(&Some(start));
+    print::plain("");
+    println!("{markdown}");
+    append_markdown(args.summary_markdown.as_deref(), &markdown)?;
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(error) = call(&args) {
+        print::error(formatdoc! {"
+            ❌ Command failed ❌
+
+            {error}
+        "});
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,64 @@
+//! Check a tarball against the provenance written next to it by `ruby_build` or `jruby_build`
+//!
+//! ```term
+//! $ cargo run --bin provenance_verify -- output/heroku-24/amd64/ruby-3.4.1.tgz
+//! ```
+
+use bullet_stream::global::print;
+use clap::Parser;
+use indoc::formatdoc;
+use shared::provenance::{self, ProvenanceError};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Tarball to verify
+    tarball: PathBuf,
+
+    /// Provenance statement, defaults to `<tarball>.intoto.json`
+    #[arg(long)]
+    provenance: Option<PathBuf>,
+}
+
+fn call(args: &Args) -> Result<(), ProvenanceError> {
+    let provenance = args
+        .provenance
+        .clone()
+        .unwrap_or_else(|| provenance::provenance_path(&args.tarball));
+
+    print::h2("Verifying provenance");
+    print::bullet(format!("Tarball {}", args.tarball.display()));
+    print::bullet(format!("Provenance {}", provenance.display()));
+
+    let statement = provenance::verify(&args.tarball, &provenance)?;
+    let definition = &statement.predicate.build_definition;
+    print::sub_bullet(format!("Built with {}", definition.external_parameters));
+    for dependency in &definition.resolved_dependencies {
+        print::sub_bullet(format!(
+            "From {} ({})",
+            dependency.uri,
+            dependency
+                .digest
+                .iter()
+                .map(|(algorithm, value)| format!("{algorithm}:{value}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    match call(&args) {
+        Ok(()) => print::bullet("Verified"),
+        Err(error) => {
+            print::error(formatdoc! {"
+                ❌ Command failed ❌
+
+                {error}
+            "});
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Write a CycloneDX SBOM for a built Ruby or JRuby tarball
+//!
+//! ```term
+//! $ cargo run --bin sbom -- output/heroku-24/amd64/ruby-3.4.1.tgz
+//! ```
+
+use bullet_stream::global::print;
+use clap::Parser;
+use fs_err as fs;
+use indoc::formatdoc;
+use shared::sbom;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Tarball to inspect, named like `ruby-<version>.tgz` or `ruby-<stdlib>-jruby-<version>.tgz`
+    /// (`.tar.zst` and `.tar.xz` work too)
+    tarball: PathBuf,
+
+    /// Where to write the SBOM, defaults to `<tarball>.cdx.json`
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn call(args: &Args) -> Result<(), Box<dyn Error>> {
+    let output = args.output.clone().unwrap_or_else(|| {
+        let mut name = args.tarball.file_name().unwrap_or_default().to_os_string();
+        name.push(".cdx.json");
+        args.tarball.with_file_name(name)
+    });
+
+    print::h2("Generating SBOM");
+    print::bullet(format!("Inspecting {}", args.tarball.display()));
+    let inventory = sbom::inspect_tarball(&args.tarball)?;
+    print::sub_bullet(format!(
+        "RubyGems {}",
+        inventory.rubygems_version.as_deref().unwrap_or("not found")
+    ));
+    print::sub_bullet(format!("{} gems", inventory.gems.len()));
+    print::sub_bullet(format!(
+        "{} system libraries: {}",
+        inventory.libraries.len(),
+        inventory
+            .libraries
+            .iter()
+            .map(|library| library.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    fs::write(
+        &output,
+        serde_json::to_string_pretty(&inventory.to_cyclonedx())?,
+    )?;
+    print::bullet(format!("Wrote {}", output.display()));
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(error) = call(&args) {
+        print::error(formatdoc! {"
+            ❌ Command failed ❌
+
+            {error}
+        "});
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,192 @@
+//! What `ruby_check` and `jruby_check` found, as Markdown or JSON
+//!
+//! Both checks print a Markdown report to stdout for the GitHub step summary by default. With
+//! `--format json` they print a [`CheckResult`] instead, so verification results can be stored
+//! next to the artifacts they describe.
+
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+/// Commands run with the Ruby under test first on the `PATH`, joined with `&&`
+///
+/// Each prints one `- <label>: <value>` line, read back by [`RuntimeFacts::parse`].
+pub const FACTS_COMMANDS: [&str; 8] = [
+    "echo -n '- Rubygems version: '",
+    "gem -v",
+    "echo -n '- Ruby version: '",
+    "ruby -v",
+    "echo -n '- Platform: '",
+    r#"ruby -rrbconfig -e 'puts RbConfig::CONFIG["host"] || RUBY_PLATFORM'"#,
+    "echo -n '- YJIT: '",
+    r#"ruby -e 'puts defined?(RubyVM::YJIT) ? "available" : "unavailable"'"#,
+];
+
+/// Facts about the Ruby under test, from the output of [`FACTS_COMMANDS`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RuntimeFacts {
+    /// `ruby -v`
+    pub ruby_version: Option<String>,
+    pub rubygems_version: Option<String>,
+    /// Target triple Ruby was built for, e.g. `x86_64-pc-linux-gnu`
+    pub platform: Option<String>,
+    pub yjit: Option<bool>,
+}
+
+impl RuntimeFacts {
+    pub fn parse(output: &str) -> Self {
+        let mut facts = Self::default();
+        for line in output.lines() {
+            let Some((label, value)) = line
+                .strip_prefix("- ")
+                .and_then(|line| line.split_once(": "))
+            else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match label {
+                "Rubygems version" => facts.rubygems_version = Some(value),
+                "Ruby version" => facts.ruby_version = Some(value),
+                "Platform" => facts.platform = Some(value),
+                "YJIT" => facts.yjit = Some(value == "available"),
+                _ => {}
+            }
+        }
+        facts
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    pub duration_secs: f64,
+    pub output: String,
+}
+
+/// Results from one Docker image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageResult {
+    pub image: String,
+    /// `None` when Ruby failed to run
+    pub facts: Option<RuntimeFacts>,
+    pub tests: Vec<TestResult>,
+}
+
+/// The document printed with `--format json`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    /// `ruby` or `jruby`
+    pub engine: &'static str,
+    pub version: String,
+    pub base_image: String,
+    pub arch: String,
+    pub passed: bool,
+    pub images: Vec<ImageResult>,
+    pub failures: Vec<String>,
+    pub duration_secs: f64,
+}
+
+impl CheckResult {
+    pub fn new(
+        engine: &'static str,
+        version: impl ToString,
+        base_image: impl ToString,
+        arch: impl ToString,
+    ) -> Self {
+        Self {
+            engine,
+            version: version.to_string(),
+            base_image: base_image.to_string(),
+            arch: arch.to_string(),
+            passed: true,
+            images: Vec::new(),
+            failures: Vec::new(),
+            duration_secs: 0.0,
+        }
+    }
+
+    /// Record the outcome, `passed` is true when there are no `failures`
+    pub fn finish(mut self, failures: Vec<String>, duration: Duration) -> Self {
+        self.passed = failures.is_empty();
+        self.failures = failures;
+        self.duration_secs = duration.as_secs_f64();
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("check result serializes to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_facts() {
+        let facts = RuntimeFacts::parse(indoc::indoc! {"
+            - Rubygems version: 3.6.2
+            - Ruby version: ruby 3.4.1 (2024-12-25 revision 48d4efcb85) +PRISM [x86_64-linux]
+            - Platform: x86_64-pc-linux-gnu
+            - YJIT: available
+        "});
+        assert_eq!(
+            facts,
+            RuntimeFacts {
+                ruby_version: Some(
+                    "ruby 3.4.1 (2024-12-25 revision 48d4efcb85) +PRISM [x86_64-linux]".to_string()
+                ),
+                rubygems_version: Some("3.6.2".to_string()),
+                platform: Some("x86_64-pc-linux-gnu".to_string()),
+                yjit: Some(true),
+            }
+        );
+        assert_eq!(RuntimeFacts::parse("- YJIT: unavailable").yjit, Some(false));
+    }
+
+    #[test]
+    fn check_result_serializes() {
+        let mut result = CheckResult::new("ruby", "3.4.1", "heroku-24", "amd64");
+        result.images.push(ImageResult {
+            image: "heroku/heroku:24".to_string(),
+            facts: Some(RuntimeFacts::default()),
+            tests: vec![TestResult {
+                name: "zlib".to_string(),
+                status: TestStatus::Passed,
+                duration_secs: 0.25,
+                output: "zlib 1.3\n".to_string(),
+            }],
+        });
+        let result = result.finish(vec!["boom".to_string()], Duration::from_secs(2));
+
+        let json: serde_json::Value = serde_json::from_str(&result.to_json()).unwrap();
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["failures"], serde_json::json!(["boom"]));
+        assert_eq!(json["duration_secs"], 2.0);
+        assert_eq!(
+            json["images"][0]["tests"][0],
+            serde_json::json!({
+                "name": "zlib",
+                "status": "passed",
+                "duration_secs": 0.25,
+                "output": "zlib 1.3\n",
+            })
+        );
+        assert_eq!(json["images"][0]["facts"]["yjit"], serde_json::Value::Null);
+    }
+}
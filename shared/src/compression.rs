@@ -0,0 +1,157 @@
+//! Compression formats for the tarballs we produce
+//!
+//! Gzip is the default and what the buildpack downloads today. Zstd and xz make smaller
+//! archives; `compression_report` shows the size and time trade-off for a given tarball.
+
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+/// Zstd level, 19 is the highest before the memory hungry `--ultra` levels
+pub const ZSTD_LEVEL: i32 = 19;
+/// Xz preset, the `xz` command line default
+pub const XZ_PRESET: u32 = 6;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    #[default]
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 3] = [Compression::Gzip, Compression::Zstd, Compression::Xz];
+
+    /// File extension including the leading dot, e.g. `.tgz`
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".tgz",
+            Compression::Zstd => ".tar.zst",
+            Compression::Xz => ".tar.xz",
+        }
+    }
+
+    /// Split a file name into its stem and compression, recognizing `.tar.gz` as gzip too
+    pub fn from_file_name(name: &str) -> Option<(&str, Compression)> {
+        [
+            (".tar.gz", Compression::Gzip),
+            (".tgz", Compression::Gzip),
+            (".tar.zst", Compression::Zstd),
+            (".tar.xz", Compression::Xz),
+        ]
+        .into_iter()
+        .find_map(|(extension, compression)| {
+            name.strip_suffix(extension).map(|stem| (stem, compression))
+        })
+    }
+
+    /// Wrap `writer` so everything written to it is compressed, call [`Encoder::finish`] when done
+    pub fn encoder<W: Write>(&self, writer: W) -> io::Result<Encoder<W>> {
+        Ok(match self {
+            Compression::Gzip => Encoder::Gzip(
+                // A fixed (zero) mtime and OS byte keep the gzip header stable between runs
+                flate2::GzBuilder::new()
+                    .mtime(0)
+                    .operating_system(255)
+                    .write(writer, flate2::Compression::best()),
+            ),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, ZSTD_LEVEL)?),
+            Compression::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(writer, XZ_PRESET)),
+        })
+    }
+
+    /// Wrap `reader` so reading from it decompresses
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+            Compression::Xz => Box::new(liblzma::read::XzDecoder::new(reader)),
+        })
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+        })
+    }
+}
+
+/// A compressing writer from [`Compression::encoder`]
+pub enum Encoder<W: Write> {
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Xz(liblzma::write::XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Write any buffered data and the format's trailer, returning the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Xz(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_format() {
+        let input = "ruby ".repeat(1000);
+        for compression in Compression::ALL {
+            let mut encoder = compression.encoder(Vec::new()).unwrap();
+            encoder.write_all(input.as_bytes()).unwrap();
+            let compressed = encoder.finish().unwrap();
+            assert!(compressed.len() < input.len(), "{compression}");
+
+            let mut output = String::new();
+            compression
+                .decoder(compressed.as_slice())
+                .unwrap()
+                .read_to_string(&mut output)
+                .unwrap();
+            assert_eq!(output, input, "{compression}");
+        }
+    }
+
+    #[test]
+    fn extensions_round_trip() {
+        for compression in Compression::ALL {
+            let name = format!("ruby-3.4.1{}", compression.extension());
+            assert_eq!(
+                Compression::from_file_name(&name),
+                Some(("ruby-3.4.1", compression))
+            );
+        }
+        assert_eq!(
+            Compression::from_file_name("jruby-dist-9.4.7.0-bin.tar.gz"),
+            Some(("jruby-dist-9.4.7.0-bin", Compression::Gzip))
+        );
+        assert_eq!(Compression::from_file_name("ruby-3.4.1.zip"), None);
+    }
+}
@@ -1,10 +1,22 @@
 //! Logic and types for working with GitHub API
 
-use crate::with_retries;
-use reqwest::Url;
-use reqwest::header::{HeaderMap, LINK, ToStrError};
+use crate::http::{self, HttpClientError, RequestClass, StatusError};
+use crate::retry::{Classify, ErrorClass, RetryPolicy};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, IF_NONE_MATCH, LINK, ToStrError};
+use reqwest::{StatusCode, Url};
 use std::fmt;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+mod auth;
+mod cache;
+mod paginate;
+pub use auth::{GitHubAuth, TOKEN_ENV_VARS, TokenResolveError, TokenSource};
+pub use cache::{CacheWriteError, ResponseCache};
+pub use paginate::{
+    DEFAULT_MAX_PAGES, Page, PageError, PageErrorKind, accumulate, paginate, paginate_with_auth,
+};
+
 use winnow::{
     Parser, Result,
     ascii::space0,
@@ -74,12 +86,159 @@ impl fmt::Debug for GitHubToken {
     }
 }
 
-/// Performs an authenticated `GET` request and returns the response headers and body.
+/// Errors from talking to the GitHub API
+#[derive(thiserror::Error, Debug)]
+pub enum GitHubError {
+    #[error(transparent)]
+    HttpClient(#[from] HttpClientError),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Status(#[from] StatusError),
+
+    #[error(
+        "GitHub rate limit exceeded requesting {url}, retry in {wait}s{budget}",
+        wait = wait.as_secs(),
+        budget = rate_limit.as_ref().map(|limit| format!(" ({limit})")).unwrap_or_default()
+    )]
+    RateLimited {
+        url: Url,
+        wait: Duration,
+        rate_limit: Option<RateLimit>,
+    },
+
+    #[error(transparent)]
+    CacheWrite(#[from] CacheWriteError),
+}
+
+impl Classify for GitHubError {
+    fn classify(&self) -> ErrorClass {
+        match self {
+            GitHubError::HttpClient(error) => error.classify(),
+            GitHubError::Http(error) => error.classify(),
+            GitHubError::Status(error) => error.classify(),
+            GitHubError::RateLimited { wait, .. } => ErrorClass::Transient {
+                retry_after: Some(*wait),
+            },
+            GitHubError::CacheWrite(_) => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// The longest a single request will wait on rate limits (including resets) before giving up
+pub const RATE_LIMIT_MAX_WAIT: Duration = Duration::from_secs(5 * 60);
+
+/// How long GitHub asks clients to back off from a secondary rate limit when it doesn't send
+/// `Retry-After` <https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api#exceeding-the-rate-limit>
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// The primary rate limit budget GitHub reports on every API response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    /// Parse the `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` headers
+    ///
+    /// Returns `None` unless all three are present and valid.
+    pub fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let number =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+
+        Some(RateLimit {
+            limit: number("x-ratelimit-limit")?,
+            remaining: number("x-ratelimit-remaining")?,
+            reset: DateTime::from_timestamp(i64::try_from(number("x-ratelimit-reset")?).ok()?, 0)?,
+        })
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Time left until the budget is replenished, zero if that is already in the past
+    pub fn until_reset(&self) -> Duration {
+        (self.reset - Utc::now()).to_std().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{remaining}/{limit} requests remaining, resets at {reset}",
+            remaining = self.remaining,
+            limit = self.limit,
+            reset = self.reset.format("%H:%M:%S UTC")
+        )
+    }
+}
+
+/// How long to wait before retrying a `403`/`429` response, or `None` when it isn't a rate limit
 ///
-/// The request sends a `Bearer` token via the `Authorization` header and uses a
-/// 30-second timeout. The entire fetch (connection, HTTP status check, and full
-/// body download) is wrapped in [`with_retries`], so a failure while streaming
-/// the body is retried rather than only connection setup and the status line.
+/// - A `Retry-After` header wins, GitHub sends it for most secondary rate limits.
+/// - An exhausted primary budget waits until `X-RateLimit-Reset` (plus a second of slack for
+///   clock skew).
+/// - Otherwise a `429`, or a `403` whose body mentions the secondary rate limit, waits one minute
+///   as recommended by GitHub.
+fn rate_limit_wait(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    rate_limit: Option<&RateLimit>,
+    body: &str,
+) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    if let Some(retry_after) = retry_after {
+        Some(retry_after)
+    } else if let Some(rate_limit) = rate_limit.filter(|limit| limit.is_exhausted()) {
+        Some(rate_limit.until_reset() + Duration::from_secs(1))
+    } else if status == StatusCode::TOO_MANY_REQUESTS
+        || body.to_lowercase().contains("secondary rate limit")
+    {
+        Some(SECONDARY_RATE_LIMIT_WAIT)
+    } else {
+        None
+    }
+}
+
+/// Turns a rate limited response into [`GitHubError::RateLimited`], and any other unsuccessful
+/// response into [`GitHubError::Status`]
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, GitHubError> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(http::error_for_status(response)?);
+    }
+
+    let error = StatusError::new(&response);
+    let rate_limit = RateLimit::from_headers(response.headers());
+    let body = response.text().await?;
+    match rate_limit_wait(status, error.retry_after, rate_limit.as_ref(), &body) {
+        Some(wait) => Err(GitHubError::RateLimited {
+            url: error.url,
+            wait,
+            rate_limit,
+        }),
+        None => Err(GitHubError::Status(error)),
+    }
+}
+
+/// Performs a `GET` request and returns the response headers and body.
+///
+/// With [`GitHubAuth::Token`] the request sends a `Bearer` token via the `Authorization`
+/// header, with [`GitHubAuth::Anonymous`] it is sent without one. Either goes over the shared
+/// [`http::client`] with the [`RequestClass::Api`] timeout. Rate limited responses are
+/// retried once the budget resets or the requested `Retry-After` passes, as long as the
+/// total wait stays under [`RATE_LIMIT_MAX_WAIT`]. The entire fetch (connection, HTTP
+/// status check, and full body download) is wrapped in a [`RetryPolicy`], so a failure
+/// while streaming the body is retried rather than only connection setup and the status line.
 ///
 /// The response headers are cloned out and returned to the caller so that pagination
 /// parsing happens outside the retry closure, ensuring deterministic parse errors
@@ -87,48 +246,145 @@ impl fmt::Debug for GitHubToken {
 ///
 /// # Errors
 ///
-/// Returns a [`reqwest::Error`] if the client cannot be built, the request fails
+/// Returns a [`GitHubError`] if the client cannot be built, the request fails
 /// after exhausting retries, the response status is not successful (see
-/// [`reqwest::Response::error_for_status`]), or the body cannot be read.
+/// [`http::error_for_status`]), or the body cannot be read. Only transient failures
+/// are retried, see [`Classify`].
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use shared::github::{self, GitHubToken};
+/// use shared::github::{self, GitHubAuth, GitHubToken};
 ///
 /// # use reqwest::Url;
-/// # async fn run() -> Result<(), reqwest::Error> {
+/// # async fn run() -> Result<(), github::GitHubError> {
 /// let url = Url::parse("https://api.github.com/repos/jruby/jruby/releases").unwrap();
-/// let token = GitHubToken::try_from("gh_token").unwrap();
-/// let response = github::get_with_auth_and_retry(&url, &token).await?;
+/// let auth = GitHubAuth::from(GitHubToken::try_from("gh_token").unwrap());
+/// let response = github::get_with_auth_and_retry(&url, &auth).await?;
 /// # let _ = response;
 /// # Ok(())
 /// # }
 /// ```
 pub async fn get_with_auth_and_retry(
     url: &Url,
-    token: &GitHubToken,
-) -> Result<GitHubResponse, reqwest::Error> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(30))
-        .user_agent("heroku-ruby-builder")
-        .build()?;
-
-    let (headers, body) = with_retries(|| async {
-        let response = client
-            .get(url.clone())
-            .bearer_auth(token.as_str())
-            .send()
-            .await?
-            .error_for_status()?;
-
-        let headers = response.headers().clone();
-        let body = response.text().await?;
-        Ok::<_, reqwest::Error>((headers, body))
-    })
-    .await?;
+    auth: &GitHubAuth,
+) -> Result<GitHubResponse, GitHubError> {
+    get_with_auth_retry_and_cache(url, auth, None).await
+}
 
-    Ok(GitHubResponse { headers, body })
+/// Like [`get_with_auth_and_retry`] but makes a conditional request when `cache` holds an
+/// `ETag` for `url`
+///
+/// A `304 Not Modified` is answered from the cache (with the fresh response's rate limit
+/// headers), anything else is returned as usual and written back to the cache. Pagination
+/// links are cached with the body, so [`GitHubPagination`] works the same on either.
+pub async fn get_with_auth_retry_and_cache(
+    url: &Url,
+    auth: &GitHubAuth,
+    cache: Option<&ResponseCache>,
+) -> Result<GitHubResponse, GitHubError> {
+    get_with_client(http::client()?, url, auth, cache).await
+}
+
+/// Like [`get_with_auth_retry_and_cache`] over `client`, so tests don't share pooled
+/// connections across runtimes
+async fn get_with_client(
+    client: &reqwest::Client,
+    url: &Url,
+    auth: &GitHubAuth,
+    cache: Option<&ResponseCache>,
+) -> Result<GitHubResponse, GitHubError> {
+    let cached = cache.and_then(|cache| cache.get(url));
+
+    // Rate limit resets can be minutes away, wait for them up to the deadline
+    let policy = RetryPolicy {
+        max_delay: RATE_LIMIT_MAX_WAIT,
+        deadline: Some(RATE_LIMIT_MAX_WAIT),
+        ..RetryPolicy::default()
+    };
+    let start = Instant::now();
+    let mut attempt = 0;
+    let (status, headers, body) = policy
+        .retry(|| {
+            attempt += 1;
+            let attempt = attempt;
+            let (policy, cached) = (&policy, &cached);
+            async move {
+                let mut request = client.get(url.clone()).timeout(RequestClass::Api.timeout());
+                if let Some(token) = auth.token() {
+                    request = request.bearer_auth(token.as_str());
+                }
+                if let Some(entry) = cached {
+                    request = request.header(IF_NONE_MATCH, &entry.etag);
+                }
+                let result = async {
+                    let response = error_for_status(request.send().await?).await?;
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let body = response.text().await?;
+                    Ok::<_, GitHubError>((status, headers, body))
+                }
+                .await;
+
+                // A rate limited request can sleep for minutes, say so before it does
+                if let Err(error) = &result
+                    && let Some(message) =
+                        rate_limit_message(error, policy, attempt, start.elapsed())
+                {
+                    bullet_stream::global::print::warning(message);
+                }
+                result
+            }
+        })
+        .await?;
+
+    match (status, cached) {
+        (StatusCode::NOT_MODIFIED, Some(entry)) => Ok(entry.into_response(headers)),
+        _ => {
+            let response = GitHubResponse { headers, body };
+            if let Some(cache) = cache {
+                cache.put(url, &response)?;
+            }
+            Ok(response)
+        }
+    }
+}
+
+/// What to log when `error` is a rate limit hit on `attempt` (1 based), `elapsed` after the first
+/// attempt: how long `policy` will wait and the remaining budget, or that it gives up instead
+fn rate_limit_message(
+    error: &GitHubError,
+    policy: &RetryPolicy,
+    attempt: u8,
+    elapsed: Duration,
+) -> Option<String> {
+    let GitHubError::RateLimited {
+        url,
+        wait,
+        rate_limit,
+    } = error
+    else {
+        return None;
+    };
+    let budget = rate_limit
+        .as_ref()
+        .map_or("no rate limit headers".to_string(), ToString::to_string);
+    let delay = policy.delay_for(attempt, Some(*wait));
+    let gives_up = attempt >= policy.max_attempts
+        || policy
+            .deadline
+            .is_some_and(|deadline| elapsed + delay > deadline);
+    Some(if gives_up {
+        format!(
+            "GitHub rate limited {url} ({budget}), giving up instead of waiting {}s",
+            wait.as_secs()
+        )
+    } else {
+        format!(
+            "GitHub rate limited {url} ({budget}), waiting {}s before retrying",
+            delay.as_secs()
+        )
+    })
 }
 
 /// Represents a response from GitHub
@@ -140,6 +396,13 @@ pub struct GitHubResponse {
     pub body: String,
 }
 
+impl GitHubResponse {
+    /// The remaining primary rate limit budget as of this response
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        RateLimit::from_headers(&self.headers)
+    }
+}
+
 /// Represents github pagination from a given [`GitHubResponse`]
 ///
 /// Does what it says on the tin
@@ -322,6 +585,175 @@ mod tests {
         next_from_headers(headers_with_link(value))
     }
 
+    fn rate_limit(remaining: u64, reset_in: i64) -> RateLimit {
+        RateLimit {
+            limit: 5000,
+            remaining,
+            reset: Utc::now() + chrono::Duration::seconds(reset_in),
+        }
+    }
+
+    #[test]
+    fn parses_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", HeaderValue::from_static("5000"));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("4987"));
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("1372700873"));
+
+        let limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(limit.limit, 5000);
+        assert_eq!(limit.remaining, 4987);
+        assert_eq!(limit.reset.timestamp(), 1372700873);
+        assert!(!limit.is_exhausted());
+        assert_eq!(limit.until_reset(), Duration::ZERO);
+        assert_eq!(
+            limit.to_string(),
+            "4987/5000 requests remaining, resets at 17:47:53 UTC"
+        );
+
+        headers.remove("x-ratelimit-reset");
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+
+    #[test]
+    fn rate_limit_wait_prefers_retry_after() {
+        let wait = rate_limit_wait(
+            StatusCode::FORBIDDEN,
+            Some(Duration::from_secs(7)),
+            Some(&rate_limit(0, 600)),
+            "",
+        );
+        assert_eq!(wait, Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn rate_limit_wait_until_reset_when_exhausted() {
+        let wait =
+            rate_limit_wait(StatusCode::FORBIDDEN, None, Some(&rate_limit(0, 30)), "").unwrap();
+        assert!(wait > Duration::from_secs(25), "got {wait:?}");
+        assert!(wait <= Duration::from_secs(31), "got {wait:?}");
+    }
+
+    #[test]
+    fn rate_limit_wait_secondary_limit() {
+        let body = r#"{"message": "You have exceeded a secondary rate limit."}"#;
+        assert_eq!(
+            rate_limit_wait(StatusCode::FORBIDDEN, None, Some(&rate_limit(10, 30)), body),
+            Some(SECONDARY_RATE_LIMIT_WAIT)
+        );
+        assert_eq!(
+            rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, None, None, ""),
+            Some(SECONDARY_RATE_LIMIT_WAIT)
+        );
+    }
+
+    #[test]
+    fn rate_limit_message_says_wait_or_give_up() {
+        let error = GitHubError::RateLimited {
+            url: Url::parse("https://api.github.com/repos/jruby/jruby/releases").unwrap(),
+            wait: Duration::from_secs(60),
+            rate_limit: Some(RateLimit {
+                limit: 60,
+                remaining: 0,
+                reset: DateTime::from_timestamp(1372700873, 0).unwrap(),
+            }),
+        };
+        let policy = RetryPolicy {
+            max_delay: RATE_LIMIT_MAX_WAIT,
+            deadline: Some(RATE_LIMIT_MAX_WAIT),
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(
+            rate_limit_message(&error, &policy, 1, Duration::ZERO).unwrap(),
+            "GitHub rate limited https://api.github.com/repos/jruby/jruby/releases (0/60 requests remaining, resets at 17:47:53 UTC), waiting 60s before retrying"
+        );
+        assert_eq!(
+            rate_limit_message(&error, &policy, 1, Duration::from_secs(4 * 60 + 30)).unwrap(),
+            "GitHub rate limited https://api.github.com/repos/jruby/jruby/releases (0/60 requests remaining, resets at 17:47:53 UTC), giving up instead of waiting 60s"
+        );
+        assert!(
+            rate_limit_message(&error, &policy, policy.max_attempts, Duration::ZERO)
+                .unwrap()
+                .contains("giving up")
+        );
+        assert_eq!(
+            rate_limit_message(
+                &GitHubError::Status(StatusError {
+                    url: Url::parse("https://api.github.com/repos/jruby/jruby/releases").unwrap(),
+                    status: StatusCode::NOT_FOUND,
+                    retry_after: None,
+                }),
+                &policy,
+                1,
+                Duration::ZERO
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn plain_forbidden_is_not_rate_limited() {
+        let body = r#"{"message": "Resource not accessible by integration"}"#;
+        assert_eq!(
+            rate_limit_wait(StatusCode::FORBIDDEN, None, Some(&rate_limit(10, 30)), body),
+            None
+        );
+        assert_eq!(
+            rate_limit_wait(
+                StatusCode::NOT_FOUND,
+                Some(Duration::from_secs(1)),
+                None,
+                ""
+            ),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unchanged_page_is_served_from_cache() {
+        use tiny_http::{Header, Response, Server};
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/releases?page=1", server.server_addr())).unwrap();
+        let link = r#"<https://api.github.com/releases?page=2>; rel="next""#;
+
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let conditional = request
+                    .headers()
+                    .iter()
+                    .any(|header| header.field.equiv("If-None-Match") && header.value == "\"v1\"");
+                let response = if conditional {
+                    Response::from_string("").with_status_code(304)
+                } else {
+                    Response::from_string(r#"[{"tag_name": "9.4.7.0"}]"#)
+                        .with_header(Header::from_bytes("ETag", "\"v1\"").unwrap())
+                        .with_header(Header::from_bytes("Link", link).unwrap())
+                };
+                let _ = request.respond(response);
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path());
+        let auth = GitHubAuth::Anonymous;
+        let client = http::HttpConfig::default().build().unwrap();
+
+        let fresh = get_with_client(&client, &url, &auth, Some(&cache))
+            .await
+            .unwrap();
+        let cached = get_with_client(&client, &url, &auth, Some(&cache))
+            .await
+            .unwrap();
+
+        assert_eq!(cached.body, fresh.body);
+        assert_eq!(
+            GitHubPagination::from(cached).unwrap().next,
+            Some(Url::parse("https://api.github.com/releases?page=2").unwrap())
+        );
+    }
+
     #[test]
     fn token_rejects_empty_strings() {
         assert_matches!(GitHubToken::try_from(""), Err(TokenError::CannotBeEmpty));
@@ -0,0 +1,297 @@
+//! Finding a GitHub token without requiring it on the command line
+//!
+//! Passing a token as an argument leaks it into shell history and process listings, so
+//! [`GitHubAuth::resolve`] also looks for one in a file, the environment, and the GitHub CLI.
+
+use super::{GitHubToken, TokenError};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variables checked for a token, in order
+pub static TOKEN_ENV_VARS: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+
+/// Where a resolved token came from
+///
+/// Safe to print, it never contains the secret itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenSource {
+    Explicit,
+    File(PathBuf),
+    Env(&'static str),
+    GhCli,
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::Explicit => write!(f, "explicit argument"),
+            TokenSource::File(path) => write!(f, "file {}", path.display()),
+            TokenSource::Env(name) => write!(f, "${name}"),
+            TokenSource::GhCli => write!(f, "`gh auth token`"),
+        }
+    }
+}
+
+/// How requests to the GitHub API are authenticated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitHubAuth {
+    Token {
+        token: GitHubToken,
+        source: TokenSource,
+    },
+    /// No token could be found
+    ///
+    /// Requests still work, but GitHub allows only 60 unauthenticated requests per hour per
+    /// IP address (versus 5,000 per hour with a token), so long listings may stop early with
+    /// [`GitHubError::RateLimited`](super::GitHubError::RateLimited) rather than waiting for
+    /// the budget to reset.
+    Anonymous,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenResolveError {
+    #[error("Cannot read GitHub token file {path} due to error {source}")]
+    CannotReadFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid GitHub token in file {path}: {source}")]
+    InvalidFile {
+        path: PathBuf,
+        #[source]
+        source: TokenError,
+    },
+}
+
+impl From<GitHubToken> for GitHubAuth {
+    fn from(token: GitHubToken) -> Self {
+        GitHubAuth::Token {
+            token,
+            source: TokenSource::Explicit,
+        }
+    }
+}
+
+impl GitHubAuth {
+    /// Use the first token found in:
+    ///
+    /// 1. `explicit` (e.g. a `--gh-token` flag), kept for backwards compatibility
+    /// 2. `file` (e.g. a mounted secret)
+    /// 3. The [`TOKEN_ENV_VARS`] environment variables, in order
+    /// 4. The output of `gh auth token`, when the GitHub CLI is installed and logged in
+    ///
+    /// falling back to [`GitHubAuth::Anonymous`] when there is none.
+    ///
+    /// Empty environment variables are treated as unset (GitHub Actions expands a missing
+    /// secret to an empty string). A `file` that is given but can't be read, or is empty, is
+    /// an error rather than silently falling through to the next source.
+    pub fn resolve(
+        explicit: Option<GitHubToken>,
+        file: Option<&Path>,
+    ) -> Result<Self, TokenResolveError> {
+        Self::resolve_with(
+            explicit,
+            file,
+            |name| std::env::var(name).ok(),
+            gh_auth_token,
+        )
+    }
+
+    /// Like [`GitHubAuth::resolve`], printing where the token came from or warning that
+    /// requests will be unauthenticated
+    pub fn resolve_and_log(
+        explicit: Option<GitHubToken>,
+        file: Option<&Path>,
+    ) -> Result<Self, TokenResolveError> {
+        use bullet_stream::global::print;
+
+        let auth = Self::resolve(explicit, file)?;
+        match &auth {
+            GitHubAuth::Token { source, .. } => {
+                print::bullet(format!("Authenticating to GitHub with token from {source}"));
+            }
+            GitHubAuth::Anonymous => print::warning(format!(
+                "No GitHub token found, making unauthenticated requests (60 per hour). \
+                Set one of {} or log in with `gh auth login`",
+                TOKEN_ENV_VARS
+                    .iter()
+                    .map(|name| format!("${name}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+        Ok(auth)
+    }
+
+    fn resolve_with(
+        explicit: Option<GitHubToken>,
+        file: Option<&Path>,
+        env: impl Fn(&str) -> Option<String>,
+        gh_cli: impl FnOnce() -> Option<String>,
+    ) -> Result<Self, TokenResolveError> {
+        if let Some(token) = explicit {
+            return Ok(token.into());
+        }
+
+        if let Some(path) = file {
+            let contents = fs_err::read_to_string(path).map_err(|source| {
+                TokenResolveError::CannotReadFile {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+            let token = GitHubToken::try_from(contents.as_str()).map_err(|source| {
+                TokenResolveError::InvalidFile {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+            return Ok(GitHubAuth::Token {
+                token,
+                source: TokenSource::File(path.to_path_buf()),
+            });
+        }
+
+        for name in TOKEN_ENV_VARS {
+            if let Some(token) =
+                env(name).and_then(|value| GitHubToken::try_from(value.as_str()).ok())
+            {
+                return Ok(GitHubAuth::Token {
+                    token,
+                    source: TokenSource::Env(name),
+                });
+            }
+        }
+
+        if let Some(token) = gh_cli().and_then(|value| GitHubToken::try_from(value.as_str()).ok()) {
+            return Ok(GitHubAuth::Token {
+                token,
+                source: TokenSource::GhCli,
+            });
+        }
+
+        Ok(GitHubAuth::Anonymous)
+    }
+
+    /// The token to send, if any
+    pub fn token(&self) -> Option<&GitHubToken> {
+        match self {
+            GitHubAuth::Token { token, .. } => Some(token),
+            GitHubAuth::Anonymous => None,
+        }
+    }
+}
+
+/// Ask the GitHub CLI for its token. `None` when `gh` isn't installed or isn't logged in.
+fn gh_auth_token() -> Option<String> {
+    let output = Command::new("gh").args(["auth", "token"]).output().ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(
+        explicit: Option<&str>,
+        file: Option<&Path>,
+        env: &[(&str, &str)],
+        gh_cli: Option<&str>,
+    ) -> Result<GitHubAuth, TokenResolveError> {
+        let env = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        GitHubAuth::resolve_with(
+            explicit.map(|token| GitHubToken::try_from(token).unwrap()),
+            file,
+            |name| env.get(name).cloned(),
+            || gh_cli.map(str::to_string),
+        )
+    }
+
+    fn source(auth: GitHubAuth) -> Option<TokenSource> {
+        match auth {
+            GitHubAuth::Token { source, .. } => Some(source),
+            GitHubAuth::Anonymous => None,
+        }
+    }
+
+    #[test]
+    fn explicit_token_wins() {
+        let auth = resolve(Some("flag"), None, &[("GITHUB_TOKEN", "env")], Some("gh")).unwrap();
+        assert_eq!(auth.token().unwrap().as_str(), "flag");
+        assert_eq!(source(auth), Some(TokenSource::Explicit));
+    }
+
+    #[test]
+    fn file_is_read_and_trimmed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        fs_err::write(&path, "from-file\n").unwrap();
+
+        let auth = resolve(None, Some(&path), &[("GITHUB_TOKEN", "env")], None).unwrap();
+        assert_eq!(auth.token().unwrap().as_str(), "from-file");
+        assert_eq!(source(auth), Some(TokenSource::File(path)));
+    }
+
+    #[test]
+    fn unreadable_or_empty_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            resolve(None, Some(&missing), &[("GITHUB_TOKEN", "env")], None),
+            Err(TokenResolveError::CannotReadFile { .. })
+        ));
+
+        let empty = dir.path().join("empty");
+        fs_err::write(&empty, "\n").unwrap();
+        assert!(matches!(
+            resolve(None, Some(&empty), &[], None),
+            Err(TokenResolveError::InvalidFile { .. })
+        ));
+    }
+
+    #[test]
+    fn env_vars_checked_in_order_skipping_empty() {
+        let auth = resolve(
+            None,
+            None,
+            &[("GITHUB_TOKEN", "a"), ("GH_TOKEN", "b")],
+            None,
+        )
+        .unwrap();
+        assert_eq!(source(auth), Some(TokenSource::Env("GITHUB_TOKEN")));
+
+        let auth = resolve(None, None, &[("GITHUB_TOKEN", ""), ("GH_TOKEN", "b")], None).unwrap();
+        assert_eq!(auth.token().unwrap().as_str(), "b");
+        assert_eq!(source(auth), Some(TokenSource::Env("GH_TOKEN")));
+    }
+
+    #[test]
+    fn falls_back_to_gh_cli_then_anonymous() {
+        let auth = resolve(None, None, &[], Some("gho_cli\n")).unwrap();
+        assert_eq!(auth.token().unwrap().as_str(), "gho_cli");
+        assert_eq!(source(auth), Some(TokenSource::GhCli));
+
+        assert_eq!(
+            resolve(None, None, &[], None).unwrap(),
+            GitHubAuth::Anonymous
+        );
+    }
+
+    #[test]
+    fn debug_and_source_do_not_leak_token() {
+        let auth = resolve(None, None, &[("GH_TOKEN", "supersecret")], None).unwrap();
+        assert!(!format!("{auth:?}").contains("supersecret"));
+        assert_eq!(source(auth).unwrap().to_string(), "$GH_TOKEN");
+    }
+}
@@ -0,0 +1,169 @@
+//! On-disk cache of GitHub responses for conditional requests
+//!
+//! GitHub answers a request carrying `If-None-Match: <etag>` with `304 Not Modified` when
+//! nothing changed, and authenticated `304`s do not count against the primary rate limit
+//! <https://docs.github.com/en/rest/using-the-rest-api/best-practices-for-using-the-rest-api#use-conditional-requests-if-appropriate>.
+//! Storing the `ETag`, `Link` header and body of each page lets an unchanged page be rebuilt
+//! locally, including its pagination links, so the page walk continues as if the full body
+//! had been downloaded again.
+
+use super::GitHubResponse;
+use reqwest::Url;
+use reqwest::header::{ETAG, HeaderMap, HeaderValue, LINK};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A directory of cached GitHub responses, one JSON file per URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    url: String,
+    pub(crate) etag: String,
+    link: Option<String>,
+    body: String,
+}
+
+impl CacheEntry {
+    /// Rebuild the cached response, keeping the headers from the fresh `304` (e.g. the current
+    /// rate limit) and filling in the cached `ETag` and `Link`
+    pub(crate) fn into_response(self, mut headers: HeaderMap) -> GitHubResponse {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(ETAG, etag);
+        }
+        if let Some(link) = self.link.and_then(|link| HeaderValue::from_str(&link).ok()) {
+            headers.insert(LINK, link);
+        }
+        GitHubResponse {
+            headers,
+            body: self.body,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Cannot write GitHub response cache {path} due to error {source}")]
+pub struct CacheWriteError {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, url: &Url) -> PathBuf {
+        let digest = Sha256::digest(url.as_str().as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(digest)))
+    }
+
+    /// The cached entry for `url`
+    ///
+    /// A missing, unreadable or corrupt entry is a cache miss rather than an error, the
+    /// request is then made unconditionally and the entry rewritten.
+    pub(crate) fn get(&self, url: &Url) -> Option<CacheEntry> {
+        fs_err::read_to_string(self.path(url))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CacheEntry>(&contents).ok())
+            .filter(|entry| entry.url == url.as_str())
+    }
+
+    /// Store `response` for `url` if it carries an `ETag`
+    pub(crate) fn put(&self, url: &Url, response: &GitHubResponse) -> Result<(), CacheWriteError> {
+        let header = |name| {
+            response
+                .headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let Some(etag) = header(ETAG) else {
+            return Ok(());
+        };
+        let entry = CacheEntry {
+            url: url.to_string(),
+            etag,
+            link: header(LINK),
+            body: response.body.clone(),
+        };
+
+        let path = self.path(url);
+        write_atomic(&self.dir, &path, &entry).map_err(|source| CacheWriteError { path, source })
+    }
+}
+
+/// Write to a temp file in the same directory then rename, so concurrent readers never see a
+/// partially written entry
+fn write_atomic(dir: &Path, path: &Path, entry: &CacheEntry) -> Result<(), std::io::Error> {
+    fs_err::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(&mut file, entry)?;
+    file.flush()?;
+    file.persist(path).map_err(|error| error.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(etag: Option<&str>, body: &str) -> GitHubResponse {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = etag {
+            headers.insert(ETAG, HeaderValue::from_str(etag).unwrap());
+        }
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(r#"<https://api.github.com/x?page=2>; rel="next""#),
+        );
+        GitHubResponse {
+            headers,
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_entry_with_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path().join("github"));
+        let url = Url::parse("https://api.github.com/x?page=1").unwrap();
+
+        assert_eq!(cache.get(&url), None);
+        cache
+            .put(&url, &response(Some(r#"W/"abc""#), "[]"))
+            .unwrap();
+
+        let entry = cache.get(&url).unwrap();
+        assert_eq!(entry.etag, r#"W/"abc""#);
+
+        let rebuilt = entry.into_response(HeaderMap::new());
+        assert_eq!(rebuilt, response(Some(r#"W/"abc""#), "[]"));
+    }
+
+    #[test]
+    fn skips_responses_without_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path());
+        let url = Url::parse("https://api.github.com/x?page=1").unwrap();
+
+        cache.put(&url, &response(None, "[]")).unwrap();
+        assert_eq!(cache.get(&url), None);
+    }
+
+    #[test]
+    fn corrupt_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path());
+        let url = Url::parse("https://api.github.com/x?page=1").unwrap();
+
+        fs_err::write(cache.path(&url), "not json").unwrap();
+        assert_eq!(cache.get(&url), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Walk every page of a GitHub listing endpoint
+//!
+//! GitHub list endpoints (releases, tags, commits, release assets, ...) return a JSON array
+//! per page and link to the following page in the `Link` header. [`paginate`] follows those
+//! links and yields each deserialized [`Page`] as a [`Stream`], so callers can act on
+//! early pages before later ones arrive. [`accumulate`] drains the stream into a
+//! [`ResultVec`], keeping the items from every page that succeeded alongside the error that
+//! stopped the walk.
+//!
+//! The page fetcher is injected so the walk can be exercised without network access, use
+//! [`paginate_with_auth`] for the real thing.
+
+use super::{
+    GitHubAuth, GitHubError, GitHubHeaderError, GitHubPagination, GitHubResponse, ResponseCache,
+};
+use crate::maybe_err::ResultVec;
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+
+/// Guard against walking an unexpectedly long (or cyclic) listing forever
+///
+/// With `per_page=100` this covers 10,000 items.
+pub const DEFAULT_MAX_PAGES: usize = 100;
+
+/// A page of a listing could not be fetched or understood
+///
+/// Pagination cannot continue past a failed page (the `next` link lives in the failed
+/// response), so this is always the last item a [`paginate`] stream yields.
+#[derive(Debug, thiserror::Error)]
+#[error("failed fetching page {url}: {source}")]
+pub struct PageError {
+    pub url: Url,
+    pub source: PageErrorKind,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PageErrorKind {
+    #[error(transparent)]
+    Fetch(#[from] GitHubError),
+
+    #[error("could not parse pagination {0}")]
+    Pagination(#[from] GitHubHeaderError),
+
+    #[error("could not parse response as JSON due to {error}. Body: {body}")]
+    Parse {
+        body: String,
+        error: serde_json::Error,
+    },
+
+    #[error("stopped after {max_pages} pages, there are more")]
+    TooManyPages { max_pages: usize },
+}
+
+/// The deserialized items of one page along with the URL they were fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub url: Url,
+    pub items: Vec<T>,
+}
+
+struct State<F> {
+    next: Option<Url>,
+    pages: usize,
+    max_pages: usize,
+    fetch: F,
+}
+
+/// Yield the items of every page starting at `first`, following `rel="next"` links
+///
+/// Stops after the last page, after the first failed page, or with
+/// [`PageErrorKind::TooManyPages`] when more than `max_pages` pages are linked.
+pub fn paginate<T, F, Fut>(
+    first: Url,
+    max_pages: usize,
+    fetch: F,
+) -> impl Stream<Item = Result<Page<T>, PageError>>
+where
+    T: DeserializeOwned,
+    F: FnMut(Url) -> Fut,
+    Fut: Future<Output = Result<GitHubResponse, GitHubError>>,
+{
+    let state = State {
+        next: Some(first),
+        pages: 0,
+        max_pages,
+        fetch,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        let url = state.next.take()?;
+        if state.pages >= state.max_pages {
+            let source = PageErrorKind::TooManyPages {
+                max_pages: state.max_pages,
+            };
+            return Some((Err(PageError { url, source }), state));
+        }
+        state.pages += 1;
+
+        match fetch_page(&mut state.fetch, &url).await {
+            Ok((items, next)) => {
+                state.next = next;
+                Some((Ok(Page { url, items }), state))
+            }
+            Err(source) => Some((Err(PageError { url, source }), state)),
+        }
+    })
+}
+
+async fn fetch_page<T, F, Fut>(
+    fetch: &mut F,
+    url: &Url,
+) -> Result<(Vec<T>, Option<Url>), PageErrorKind>
+where
+    T: DeserializeOwned,
+    F: FnMut(Url) -> Fut,
+    Fut: Future<Output = Result<GitHubResponse, GitHubError>>,
+{
+    let response = fetch(url.clone()).await?;
+    let items = serde_json::from_str(&response.body).map_err(|error| PageErrorKind::Parse {
+        body: response.body.clone(),
+        error,
+    })?;
+    let next = GitHubPagination::from(response)?.next;
+    Ok((items, next))
+}
+
+/// [`paginate`] over GitHub API requests made with `auth` (and optionally cached)
+pub fn paginate_with_auth<'a, T>(
+    first: Url,
+    auth: &'a GitHubAuth,
+    cache: Option<&'a ResponseCache>,
+) -> impl Stream<Item = Result<Page<T>, PageError>> + 'a
+where
+    T: DeserializeOwned + 'a,
+{
+    paginate(first, DEFAULT_MAX_PAGES, move |url| async move {
+        super::get_with_auth_retry_and_cache(&url, auth, cache).await
+    })
+}
+
+/// Drain a [`paginate`] stream, flattening every page's items and keeping the error (if any)
+/// that ended the walk
+pub async fn accumulate<T>(
+    pages: impl Stream<Item = Result<Page<T>, PageError>>,
+) -> ResultVec<T, PageError> {
+    let mut results = Vec::new();
+    let mut pages = std::pin::pin!(pages);
+    while let Some(page) = pages.next().await {
+        match page {
+            Ok(page) => results.extend(page.items.into_iter().map(Ok)),
+            Err(error) => results.push(Err(error)),
+        }
+    }
+    results.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, LINK};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn page(body: &str, next: Option<&str>) -> GitHubResponse {
+        let mut headers = HeaderMap::new();
+        if let Some(next) = next {
+            headers.insert(
+                LINK,
+                HeaderValue::from_str(&format!(r#"<{next}>; rel="next""#)).unwrap(),
+            );
+        }
+        GitHubResponse {
+            headers,
+            body: body.to_string(),
+        }
+    }
+
+    fn url(page: usize) -> Url {
+        Url::parse(&format!("https://api.github.com/x?page={page}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn follows_next_links_until_the_last_page() {
+        let mut errors: Vec<PageError> = Vec::new();
+        let items = accumulate(paginate::<u32, _, _>(url(1), 10, |url| async move {
+            Ok(match url.query() {
+                Some("page=1") => page("[1, 2]", Some("https://api.github.com/x?page=2")),
+                _ => page("[3]", None),
+            })
+        }))
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        assert_eq!(items, vec![1, 2, 3]);
+        assert!(errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_items_collected_before_a_failed_page() {
+        let mut errors: Vec<PageError> = Vec::new();
+        let items = accumulate(paginate::<u32, _, _>(url(1), 10, |url| async move {
+            Ok(match url.query() {
+                Some("page=1") => page("[1, 2]", Some("https://api.github.com/x?page=2")),
+                _ => page("not json", None),
+            })
+        }))
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        assert_eq!(items, vec![1, 2]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].url, url(2));
+        assert!(matches!(errors[0].source, PageErrorKind::Parse { .. }));
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_pages() {
+        let fetched = AtomicUsize::new(0);
+        let mut errors: Vec<PageError> = Vec::new();
+        let items = accumulate(paginate::<u32, _, _>(url(1), 2, |_| {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            async { Ok(page("[1]", Some("https://api.github.com/x?page=1"))) }
+        }))
+        .await
+        .unwrap_drain_errs(&mut errors);
+
+        assert_eq!(items, vec![1, 1]);
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].source,
+            PageErrorKind::TooManyPages { max_pages: 2 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn yields_pages_lazily() {
+        let fetched = AtomicUsize::new(0);
+        let pages = paginate::<u32, _, _>(url(1), 10, |_| {
+            fetched.fetch_add(1, Ordering::SeqCst);
+            async { Ok(page("[1]", Some("https://api.github.com/x?page=1"))) }
+        });
+        let mut pages = std::pin::pin!(pages);
+
+        let first = pages.next().await.unwrap().unwrap();
+        assert_eq!(first.url, url(1));
+        assert_eq!(first.items, vec![1]);
+        assert_eq!(fetched.load(Ordering::SeqCst), 1);
+    }
+}
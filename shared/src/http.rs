@@ -0,0 +1,268 @@
+//! A single HTTP client shared by every network call in a run.
+//!
+//! Building a [`reqwest::Client`] per attempt throws away its connection pool, so release
+//! checks issuing hundreds of `HEAD` requests to the same S3 host would open a fresh TLS
+//! connection for each one. Instead [`client`] lazily builds one client from
+//! [`HttpConfig::from_env`] and hands out the same instance for the rest of the process.
+//!
+//! Timeouts differ by the kind of request being made, see [`RequestClass`]. They are applied
+//! per request rather than on the client so one pool can serve all of them.
+//!
+//! Environment:
+//!
+//! - `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` are honored by reqwest's
+//!   system proxy support.
+//! - `SSL_CERT_FILE` points at a PEM bundle of additional root certificates (e.g. for a
+//!   TLS-intercepting proxy). They are merged with the built-in roots.
+
+use crate::retry::{Classify, ErrorClass};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{StatusCode, Url};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub static USER_AGENT: &str = "heroku-ruby-builder";
+pub static CA_BUNDLE_ENV: &str = "SSL_CERT_FILE";
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The kind of request being made, used to pick a timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    /// Existence checks such as `HEAD` requests against S3
+    Probe,
+    /// Small API and metadata responses (GitHub, ruby-lang.org, build properties)
+    Api,
+    /// Large archive downloads
+    Download,
+}
+
+impl RequestClass {
+    pub fn timeout(&self) -> Duration {
+        match self {
+            RequestClass::Probe => Duration::from_secs(30),
+            RequestClass::Api => Duration::from_secs(30),
+            RequestClass::Download => Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum HttpClientError {
+    #[error("Cannot read CA bundle {path} from ${CA_BUNDLE_ENV} due to error {source}")]
+    CannotReadCaBundle {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid CA bundle {path} from ${CA_BUNDLE_ENV} due to error {source}")]
+    InvalidCaBundle {
+        path: PathBuf,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("Cannot build HTTP client due to error {0}")]
+    CannotBuild(reqwest::Error),
+}
+
+impl Classify for HttpClientError {
+    fn classify(&self) -> ErrorClass {
+        ErrorClass::Permanent
+    }
+}
+
+/// A response came back with a non-success status
+///
+/// Unlike the error from [`reqwest::Response::error_for_status`] this keeps the
+/// `Retry-After` header so retries can wait as long as the server asked.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Unexpected status {status} from {url}")]
+pub struct StatusError {
+    pub url: Url,
+    pub status: StatusCode,
+    pub retry_after: Option<Duration>,
+}
+
+impl StatusError {
+    pub fn new(response: &reqwest::Response) -> Self {
+        Self {
+            url: response.url().clone(),
+            status: response.status(),
+            retry_after: retry_after(response.headers()),
+        }
+    }
+}
+
+impl Classify for StatusError {
+    fn classify(&self) -> ErrorClass {
+        ErrorClass::from_status(self.status, self.retry_after)
+    }
+}
+
+/// Returns the response when the status is successful, a [`StatusError`] otherwise
+pub fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, StatusError> {
+    if response.status().is_client_error() || response.status().is_server_error() {
+        Err(StatusError::new(&response))
+    } else {
+        Ok(response)
+    }
+}
+
+/// Parse a `Retry-After` header, either delay-seconds or an HTTP date
+///
+/// Dates in the past resolve to a zero delay.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        Some(Duration::from_secs(seconds))
+    } else {
+        let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        Some(
+            (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Settings used to build the shared client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    pub connect_timeout: Duration,
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: USER_AGENT.to_string(),
+            connect_timeout: Duration::from_secs(10),
+            ca_bundle: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn from_env() -> Self {
+        Self {
+            ca_bundle: std::env::var_os(CA_BUNDLE_ENV)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from),
+            ..Self::default()
+        }
+    }
+
+    pub fn build(&self) -> Result<reqwest::Client, HttpClientError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .connect_timeout(self.connect_timeout);
+
+        if let Some(path) = &self.ca_bundle {
+            let pem = fs_err::read(path).map_err(|source| HttpClientError::CannotReadCaBundle {
+                path: path.clone(),
+                source,
+            })?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|source| {
+                HttpClientError::InvalidCaBundle {
+                    path: path.clone(),
+                    source,
+                }
+            })?;
+            builder = builder.tls_certs_merge(certs);
+        }
+
+        builder.build().map_err(HttpClientError::CannotBuild)
+    }
+}
+
+/// Returns the process-wide client, building it from [`HttpConfig::from_env`] on first use
+///
+/// Cloning a `reqwest::Client` is cheap and shares the underlying pool, so callers that need
+/// an owned value can clone the returned reference.
+pub fn client() -> Result<&'static reqwest::Client, HttpClientError> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = HttpConfig::from_env().build()?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let future = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        headers.insert(RETRY_AFTER, future.parse().unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(80), "got {delay:?}");
+    }
+
+    #[test]
+    fn retry_after_missing_or_garbage() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn download_timeout_is_longest() {
+        assert!(RequestClass::Download.timeout() > RequestClass::Api.timeout());
+        assert!(RequestClass::Download.timeout() > RequestClass::Probe.timeout());
+    }
+
+    #[test]
+    fn client_is_reused() {
+        let a = client().unwrap();
+        let b = client().unwrap();
+        assert!(std::ptr::eq(a, b));
+    }
+
+    #[test]
+    fn missing_ca_bundle_errors() {
+        let config = HttpConfig {
+            ca_bundle: Some(PathBuf::from("/does/not/exist.pem")),
+            ..HttpConfig::default()
+        };
+        assert!(matches!(
+            config.build(),
+            Err(HttpClientError::CannotReadCaBundle { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_ca_bundle_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.pem");
+        fs_err::write(
+            &path,
+            "-----BEGIN CERTIFICATE-----\nnope\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let config = HttpConfig {
+            ca_bundle: Some(path),
+            ..HttpConfig::default()
+        };
+        assert!(config.build().is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::base_image::DistroVersion;
-use crate::{Error, TarDownloadPath, download_tar};
+use crate::{Compression, Error, TarDownloadPath, download_tar};
 use chrono::{DateTime, Utc};
 use fs_err::{self as fs};
 use fs2::FileExt;
@@ -44,13 +44,23 @@ pub async fn inventory_check(contents: &str) -> Result<(), Error> {
         .parse::<Inventory<GemVersion, Sha256, ArtifactMetadata>>()
         .map_err(|e| Error::Other(format!("Could not parse inventory. Error: {e}")))?;
 
+    let client = crate::http::client().map_err(Error::HttpClient)?;
+    let mut errors = Vec::new();
     let mut set = tokio::task::JoinSet::new();
     for artifact in inventory.artifacts {
+        let compression = match url_compression(&artifact.url) {
+            Ok(compression) => compression,
+            Err(message) => {
+                errors.push(message);
+                continue;
+            }
+        };
+        let client = client.clone();
         set.spawn(async move {
             let temp = tempfile::tempdir().map_err(|e| format!("Error {e}"))?;
-            let path = temp.path().join("file.tar");
+            let path = temp.path().join(format!("file{}", compression.extension()));
 
-            download_tar(&artifact.url, &TarDownloadPath(path.clone()))
+            download_tar(&client, &artifact.url, &TarDownloadPath(path.clone()))
                 .await
                 .map_err(|e| format!("Error {e}"))?;
 
@@ -78,7 +88,6 @@ pub async fn inventory_check(contents: &str) -> Result<(), Error> {
         });
     }
 
-    let mut errors = Vec::new();
     while let Some(joined) = set.join_next().await {
         match joined {
             Ok(Ok(())) => {}
@@ -94,6 +103,26 @@ pub async fn inventory_check(contents: &str) -> Result<(), Error> {
     }
 }
 
+/// Compression of the artifact at `url`, from its file extension
+///
+/// An inventory entry is only useful to the buildpack if the extension names a format it can
+/// extract, see [`Compression::extension`].
+fn url_compression(url: &str) -> Result<Compression, String> {
+    let file_name = url.rsplit('/').next().unwrap_or_default();
+    Compression::from_file_name(file_name)
+        .map(|(_, compression)| compression)
+        .ok_or_else(|| {
+            format!(
+                "Unknown compression for {url}, expected one of {}",
+                Compression::ALL
+                    .iter()
+                    .map(|compression| format!("`{}`", compression.extension()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
 fn atomic_file_contents<F, T>(path: &Path, f: F) -> Result<T, Box<dyn std::error::Error>>
 where
     F: FnOnce(&mut std::fs::File, &str) -> Result<T, Box<dyn std::error::Error>>,
@@ -222,6 +251,25 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_url_compression() {
+        let base =
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-24/amd64";
+        assert_eq!(
+            url_compression(&format!("{base}/ruby-3.4.1.tgz")),
+            Ok(Compression::Gzip)
+        );
+        assert_eq!(
+            url_compression(&format!("{base}/ruby-3.1.4-jruby-9.4.8.0.tar.zst")),
+            Ok(Compression::Zstd)
+        );
+        assert!(
+            url_compression(&format!("{base}/ruby-3.4.1.zip"))
+                .unwrap_err()
+                .contains("`.tgz`, `.tar.zst`, `.tar.xz`")
+        );
+    }
+
     #[test]
     fn test_same_url_different_checksum_raises_error() {
         let a = Artifact {
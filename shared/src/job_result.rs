@@ -0,0 +1,207 @@
+//! A JSON document describing what a build job did
+//!
+//! `ruby_build` and `jruby_build` write a [`JobResult`] to `--job-result <PATH>` so later
+//! workflow steps (checks, uploads, inventory updates) can read the outputs, their checksums,
+//! and how they were produced instead of re-deriving paths from the inputs.
+
+use crate::BuildStatus;
+use fs_err as fs;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// How a job ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Success,
+    Skipped,
+    Error,
+}
+
+impl From<BuildStatus> for JobStatus {
+    fn from(status: BuildStatus) -> Self {
+        match status {
+            BuildStatus::Success => JobStatus::Success,
+            BuildStatus::Skipped => JobStatus::Skipped,
+        }
+    }
+}
+
+/// A file written by a build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub sha256: String,
+    /// Copy of `path` with the first seven characters of the SHA in its name, if one was made
+    pub sha_seven_path: Option<PathBuf>,
+    /// Provenance statement for `path`, see [`crate::provenance`]
+    pub provenance_path: Option<PathBuf>,
+}
+
+/// What a build function hands back to its `main` on success
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    pub status: BuildStatus,
+    /// File name of the artifact, the same for every output
+    pub artifact_name: String,
+    /// Empty when the build was skipped
+    pub outputs: Vec<OutputFile>,
+    /// Versions of the tools used, keyed by tool name
+    pub tools: BTreeMap<String, String>,
+}
+
+/// The document written to `--job-result`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobResult {
+    pub status: JobStatus,
+    pub version: String,
+    pub base_image: String,
+    /// `None` for architecture independent builds
+    pub arch: Option<String>,
+    pub artifact_name: Option<String>,
+    pub outputs: Vec<OutputFile>,
+    pub duration_secs: f64,
+    pub tools: BTreeMap<String, String>,
+    pub error: Option<String>,
+}
+
+impl JobResult {
+    pub fn new<E: std::fmt::Display>(
+        version: impl ToString,
+        base_image: impl ToString,
+        arch: Option<impl ToString>,
+        result: &Result<BuildOutput, E>,
+        duration: Duration,
+    ) -> Self {
+        let (status, artifact_name, outputs, tools, error) = match result {
+            Ok(output) => (
+                output.status.into(),
+                Some(output.artifact_name.clone()),
+                output.outputs.clone(),
+                output.tools.clone(),
+                None,
+            ),
+            Err(error) => (
+                JobStatus::Error,
+                None,
+                Vec::new(),
+                BTreeMap::new(),
+                Some(error.to_string()),
+            ),
+        };
+        Self {
+            status,
+            version: version.to_string(),
+            base_image: base_image.to_string(),
+            arch: arch.map(|arch| arch.to_string()),
+            artifact_name,
+            outputs,
+            duration_secs: duration.as_secs_f64(),
+            tools,
+            error,
+        }
+    }
+
+    /// Writes the result as pretty JSON to `path`
+    ///
+    /// Returns `Ok(())` when `path` is `None` (no-op), so callers can pass
+    /// the optional `--job-result` argument directly.
+    pub fn write(&self, path: Option<&Path>) -> std::io::Result<()> {
+        let Some(path) = path else { return Ok(()) };
+        fs::write(
+            path,
+            serde_json::to_string_pretty(self).expect("job result serializes to JSON"),
+        )
+    }
+}
+
+/// First line of `program --version`, or `None` when it can't be run
+pub fn tool_version(program: &str) -> Option<String> {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|line| line.trim().to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_result_serializes_success() {
+        let output = BuildOutput {
+            status: BuildStatus::Success,
+            artifact_name: "ruby-3.4.1.tgz".to_string(),
+            outputs: vec![OutputFile {
+                path: PathBuf::from("output/heroku-24/amd64/ruby-3.4.1.tgz"),
+                sha256: "abcdef0123".to_string(),
+                sha_seven_path: Some(PathBuf::from(
+                    "output/heroku-24/amd64/ruby-3.4.1-abcdef0.tgz",
+                )),
+                provenance_path: None,
+            }],
+            tools: BTreeMap::from([("docker".to_string(), "Docker version 27".to_string())]),
+        };
+        let result = JobResult::new(
+            "3.4.1",
+            "heroku-24",
+            Some("amd64"),
+            &Ok::<_, String>(output),
+            Duration::from_millis(1500),
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&result).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "status": "success",
+                "version": "3.4.1",
+                "base_image": "heroku-24",
+                "arch": "amd64",
+                "artifact_name": "ruby-3.4.1.tgz",
+                "outputs": [{
+                    "path": "output/heroku-24/amd64/ruby-3.4.1.tgz",
+                    "sha256": "abcdef0123",
+                    "sha_seven_path": "output/heroku-24/amd64/ruby-3.4.1-abcdef0.tgz",
+                    "provenance_path": null,
+                }],
+                "duration_secs": 1.5,
+                "tools": {"docker": "Docker version 27"},
+                "error": null,
+            })
+        );
+    }
+
+    #[test]
+    fn job_result_records_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job_result.json");
+        let result = JobResult::new(
+            "9.4.7.0",
+            "heroku-24",
+            None::<String>,
+            &Err::<BuildOutput, _>("download failed"),
+            Duration::ZERO,
+        );
+        result.write(Some(&path)).unwrap();
+        result.write(None).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["status"], "error");
+        assert_eq!(json["arch"], serde_json::Value::Null);
+        assert_eq!(json["error"], "download failed");
+        assert_eq!(json["outputs"], serde_json::json!([]));
+    }
+}
@@ -1,10 +1,10 @@
 use fs_err::{self as fs, File, PathExt};
-use fun_run::CommandWithName;
+use http::RequestClass;
 use libherokubuildpack::inventory::artifact::Arch;
 use reqwest::Url;
+use retry::{Classify, ErrorClass, RetryPolicy};
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::time::Duration;
 
 pub const MAX_RETRY_ATTEMPTS: u8 = 3;
@@ -14,33 +14,37 @@ pub const RETRY_DELAY: Duration = Duration::from_secs(1);
 #[cfg(test)]
 pub const RETRY_DELAY: Duration = Duration::from_millis(0);
 
+/// Retries transient failures using the default [`RetryPolicy`]
 pub async fn with_retries<T, E, F, Fut>(f: F) -> Result<T, E>
 where
-    F: Fn() -> Fut,
+    E: Classify,
+    F: FnMut() -> Fut,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
-    let mut attempts = 0;
-    loop {
-        attempts += 1;
-        match f().await {
-            Ok(val) => return Ok(val),
-            Err(error) => {
-                if attempts >= MAX_RETRY_ATTEMPTS {
-                    return Err(error);
-                }
-                tokio::time::sleep(RETRY_DELAY).await;
-            }
-        }
-    }
+    RetryPolicy::default().retry(f).await
 }
 
+pub mod audit;
 mod base_image;
+pub mod check_result;
+mod compression;
 mod download_ruby_version;
 pub mod github;
+pub mod http;
 mod inventory_help;
+pub mod job_result;
+pub mod linkage;
 pub mod maybe_err;
-
-pub use base_image::{BaseImage, build_matrix};
+pub mod provenance;
+pub mod release_check;
+pub mod relocatable;
+pub mod retry;
+pub mod sbom;
+pub mod settle;
+pub mod summary;
+
+pub use base_image::{BaseImage, ImageFlavor, build_matrix};
+pub use compression::{Compression, Encoder};
 pub use download_ruby_version::RubyDownloadVersion;
 
 pub static S3_BASE_URL: &str = "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com";
@@ -94,22 +98,197 @@ impl AsRef<Path> for TarDownloadPath {
     }
 }
 
+/// Extract a tarball into `workspace`, decompressing according to its extension (gzip when
+/// it isn't recognized)
+///
+/// Entries are written one at a time rather than with `tar::Archive::unpack`, which fails on
+/// hard links to symlinks (<https://github.com/alexcrichton/tar-rs/issues/369>). Entries that
+/// would land outside `workspace`, either through `..`, an absolute path, or by writing through
+/// a symlink extracted earlier, are rejected. File and directory permissions are preserved.
 pub fn untar_to_dir(tar_path: &TarDownloadPath, workspace: &Path) -> Result<(), Error> {
+    let tar_file = tar_path.as_ref();
     fs::create_dir_all(workspace).map_err(Error::FsError)?;
+    let file = fs::File::open(tar_file).map_err(Error::FsError)?;
+    let compression =
+        Compression::from_file_name(&tar_file.file_name().unwrap_or_default().to_string_lossy())
+            .map(|(_, compression)| compression)
+            .unwrap_or_default();
+    let mut archive = tar::Archive::new(compression.decoder(file).map_err(Error::FsError)?);
+
+    // Applied last so read-only directories can still be written into while extracting
+    let mut dir_modes = Vec::new();
+    for entry in archive.entries().map_err(Error::FsError)? {
+        let mut entry = entry.map_err(Error::FsError)?;
+        let name = entry.path().map_err(Error::FsError)?.to_path_buf();
+        let io_error = |source| Error::UntarError {
+            tar_file: tar_file.to_path_buf(),
+            entry: name.clone(),
+            source,
+        };
+        let unsafe_entry = |reason: &str| Error::UnsafeTarEntry {
+            tar_file: tar_file.to_path_buf(),
+            entry: name.clone(),
+            reason: reason.to_string(),
+        };
+
+        let relative = safe_relative_path(&name).ok_or_else(|| {
+            unsafe_entry("absolute paths and `..` would extract outside the directory")
+        })?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let dest = workspace.join(&relative);
+        if let Some(parent) = relative.parent() {
+            create_real_dirs(workspace, parent).map_err(|error| match error {
+                DirError::Symlink => unsafe_entry("a parent directory is a symlink"),
+                DirError::Missing => unreachable!("missing directories are created"),
+                DirError::Io(source) => io_error(source),
+            })?;
+        }
+        let mode = entry.header().mode().map_err(io_error)? & 0o7777;
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                match fs::symlink_metadata(&dest) {
+                    Ok(metadata) if metadata.is_symlink() => {
+                        return Err(unsafe_entry("the directory is already a symlink"));
+                    }
+                    Ok(metadata) if metadata.is_dir() => {}
+                    Ok(_) => {
+                        remove_existing(&dest).map_err(io_error)?;
+                        fs::create_dir(&dest).map_err(io_error)?;
+                    }
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        fs::create_dir(&dest).map_err(io_error)?;
+                    }
+                    Err(error) => return Err(io_error(error)),
+                }
+                dir_modes.push((dest, mode));
+            }
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                remove_existing(&dest).map_err(io_error)?;
+                let mut file = fs::File::create(&dest).map_err(io_error)?;
+                std::io::copy(&mut entry, &mut file).map_err(io_error)?;
+                set_mode(&dest, mode).map_err(io_error)?;
+            }
+            tar::EntryType::Symlink => {
+                let target = entry
+                    .link_name()
+                    .map_err(io_error)?
+                    .ok_or_else(|| unsafe_entry("symlink without a target"))?;
+                remove_existing(&dest).map_err(io_error)?;
+                fs::os::unix::fs::symlink(target, &dest).map_err(io_error)?;
+            }
+            tar::EntryType::Link => {
+                let target = entry
+                    .link_name()
+                    .map_err(io_error)?
+                    .and_then(|target| safe_relative_path(&target))
+                    .filter(|target| !target.as_os_str().is_empty())
+                    .ok_or_else(|| unsafe_entry("hard link target is outside the directory"))?;
+                if let Some(parent) = target.parent() {
+                    existing_real_dirs(workspace, parent).map_err(|error| match error {
+                        DirError::Symlink => {
+                            unsafe_entry("a parent directory of the hard link target is a symlink")
+                        }
+                        DirError::Missing => unsafe_entry("hard link target doesn't exist"),
+                        DirError::Io(source) => io_error(source),
+                    })?;
+                }
+                let target = workspace.join(target);
+                match fs::symlink_metadata(&target) {
+                    Ok(_) => {}
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        return Err(unsafe_entry("hard link target doesn't exist"));
+                    }
+                    Err(error) => return Err(io_error(error)),
+                }
+                remove_existing(&dest).map_err(io_error)?;
+                // Linking doesn't follow symlinks, so a hard link to a symlink is a second symlink
+                fs::hard_link(&target, &dest).map_err(io_error)?;
+            }
+            // Metadata for the whole archive (`git archive` writes the commit) or a PAX header
+            // that wasn't folded into the entry after it, neither is a file
+            tar::EntryType::XGlobalHeader | tar::EntryType::XHeader => {}
+            other => {
+                return Err(unsafe_entry(&format!("unsupported entry type {other:?}")));
+            }
+        }
+    }
+
+    // Deepest first so a read-only parent doesn't stop its children being updated
+    dir_modes.sort_by(|(a, _), (b, _)| b.cmp(a));
+    for (dir, mode) in dir_modes {
+        set_mode(&dir, mode).map_err(Error::FsError)?;
+    }
+
+    Ok(())
+}
 
-    // Shelling out due to https://github.com/alexcrichton/tar-rs/issues/369
-    let mut cmd = Command::new("bash");
-    cmd.arg("-c");
-    cmd.arg(format!(
-        "tar xzf {tar_file} -C {out_dir}",
-        tar_file = tar_path.as_ref().display(),
-        out_dir = workspace.display()
-    ));
-    cmd.named_output().map_err(Error::CmdError)?;
+/// `path` without `.` components, or `None` if it's absolute or has `..`
+fn safe_relative_path(path: &Path) -> Option<PathBuf> {
+    path.components()
+        .try_fold(PathBuf::new(), |mut out, component| match component {
+            std::path::Component::Normal(part) => {
+                out.push(part);
+                Some(out)
+            }
+            std::path::Component::CurDir => Some(out),
+            _ => None,
+        })
+}
+
+enum DirError {
+    Symlink,
+    Missing,
+    Io(std::io::Error),
+}
+
+/// Create `relative` below `root`, refusing to go through symlinks
+fn create_real_dirs(root: &Path, relative: &Path) -> Result<(), DirError> {
+    walk_real_dirs(root, relative, |dir| {
+        fs::create_dir(dir).map_err(DirError::Io)
+    })
+}
+
+/// Check `relative` exists below `root` without going through symlinks
+fn existing_real_dirs(root: &Path, relative: &Path) -> Result<(), DirError> {
+    walk_real_dirs(root, relative, |_| Err(DirError::Missing))
+}
 
+fn walk_real_dirs(
+    root: &Path,
+    relative: &Path,
+    missing: impl Fn(&Path) -> Result<(), DirError>,
+) -> Result<(), DirError> {
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_symlink() => return Err(DirError::Symlink),
+            Ok(_) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => missing(&current)?,
+            Err(error) => return Err(DirError::Io(error)),
+        }
+    }
     Ok(())
 }
 
+/// Later entries replace earlier ones, without writing through a symlink that's in the way
+fn remove_existing(path: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Command failed {0}")]
@@ -128,6 +307,12 @@ pub enum Error {
     #[error("Failed to download {0}")]
     FailedRequest(reqwest::Error),
 
+    #[error(transparent)]
+    HttpClient(http::HttpClientError),
+
+    #[error(transparent)]
+    HttpStatus(http::StatusError),
+
     #[error("Invalid ruby version {version} reason: {reason}")]
     InvalidVersion { version: String, reason: String },
 
@@ -142,10 +327,39 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[error("Could not extract {entry} from {tar_file} due to error {source}")]
+    UntarError {
+        tar_file: PathBuf,
+        entry: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Refusing to extract {entry} from {tar_file}: {reason}")]
+    UnsafeTarEntry {
+        tar_file: PathBuf,
+        entry: PathBuf,
+        reason: String,
+    },
+
+    #[error("Invalid SOURCE_DATE_EPOCH {0:?}, expected seconds since the Unix epoch")]
+    InvalidSourceDateEpoch(String),
+
     #[error("Error {0}")]
     Other(String),
 }
 
+impl Classify for Error {
+    fn classify(&self) -> ErrorClass {
+        match self {
+            Error::FailedRequest(error) => error.classify(),
+            Error::HttpStatus(error) => error.classify(),
+            Error::UrlToFileError { source, .. } => source.classify(),
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
 pub fn source_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("..")
@@ -171,47 +385,50 @@ pub fn validate_version_for_stack(
 }
 
 /// Performs an HTTP HEAD request to check if a URL returns a successful status.
-pub async fn s3_url_exists(url: Url) -> Result<bool, Error> {
-    with_retries(|| s3_url_exists_inner(url.clone())).await
+///
+/// Binaries pass [`http::client`], tests build their own so pooled connections don't outlive
+/// the runtime that opened them.
+pub async fn s3_url_exists(client: &reqwest::Client, url: Url) -> Result<bool, Error> {
+    with_retries(|| s3_url_exists_inner(client, url.clone())).await
 }
 
-async fn s3_url_exists_inner(url: Url) -> Result<bool, Error> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .map_err(Error::FailedRequest)?;
+async fn s3_url_exists_inner(client: &reqwest::Client, url: Url) -> Result<bool, Error> {
     let response = client
-        .head(url.clone())
+        .head(url)
+        .timeout(RequestClass::Probe.timeout())
         .send()
         .await
         .map_err(Error::FailedRequest)?;
     match response.status() {
         status if status.is_success() => Ok(true),
         reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::FORBIDDEN => Ok(false),
-        status => Err(Error::Other(format!(
-            "Unexpected status {status} checking {url}"
-        ))),
+        _ => Err(Error::HttpStatus(http::StatusError::new(&response))),
     }
 }
 
-pub async fn download_tar(url: &str, path: &TarDownloadPath) -> Result<(), Error> {
-    with_retries(|| download_tar_inner(url, path)).await
+/// Download `url` to `path`, see [`s3_url_exists`] for which `client` to pass
+pub async fn download_tar(
+    client: &reqwest::Client,
+    url: &str,
+    path: &TarDownloadPath,
+) -> Result<(), Error> {
+    with_retries(|| download_tar_inner(client, url, path)).await
 }
 
-async fn download_tar_inner(url: &str, path: &TarDownloadPath) -> Result<(), Error> {
+async fn download_tar_inner(
+    client: &reqwest::Client,
+    url: &str,
+    path: &TarDownloadPath,
+) -> Result<(), Error> {
     use tokio::io::AsyncWriteExt;
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .map_err(Error::FailedRequest)?;
     let mut response = client
         .get(url)
+        .timeout(RequestClass::Download.timeout())
         .send()
         .await
-        .map_err(Error::FailedRequest)?
-        .error_for_status()
-        .map_err(Error::FailedRequest)?;
+        .map_err(Error::FailedRequest)
+        .and_then(|response| http::error_for_status(response).map_err(Error::HttpStatus))?;
 
     // fs_err::tokio keeps the path-annotated error messages the codebase relies on.
     let mut dest = fs_err::tokio::File::create(path.as_ref())
@@ -248,24 +465,131 @@ pub fn output_ruby_tar_path(
     version: &RubyDownloadVersion,
     base_image: &BaseImage,
     cpu_architecture: Option<&Arch>,
+    compression: Compression,
 ) -> PathBuf {
     output_target_dir(output, base_image, cpu_architecture)
-        .join(format!("ruby-{}.tgz", version.bundler_format()))
+        .join(ruby_tar_file_name(version, compression))
+}
+
+/// File name of a Ruby build, the same on every base image and architecture
+pub fn ruby_tar_file_name(version: &RubyDownloadVersion, compression: Compression) -> String {
+    format!(
+        "ruby-{}{}",
+        version.bundler_format(),
+        compression.extension()
+    )
+}
+
+/// How [`tar_dir_to_file`] records entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarMode {
+    /// Filesystem order, mtimes, owners and permissions as they are on disk
+    Preserve,
+    /// Byte for byte the same archive whenever the contents are the same: entries sorted by
+    /// path, every mtime set to `mtime`, owned by `root:root` (uid and gid 0), permissions
+    /// normalized to 0755 for directories and executables and 0644 for everything else, and
+    /// a gzip header without a timestamp
+    Reproducible { mtime: u64 },
 }
 
-pub fn tar_dir_to_file(compiled_dir: &Path, tar_file: &File) -> Result<(), Error> {
-    let enc = flate2::write::GzEncoder::new(tar_file, flate2::Compression::best());
+impl TarMode {
+    /// [`TarMode::Reproducible`] using `$SOURCE_DATE_EPOCH` as the mtime, or `0` when unset
+    ///
+    /// <https://reproducible-builds.org/docs/source-date-epoch/>
+    pub fn reproducible_from_env() -> Result<Self, Error> {
+        let mtime = match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .map_err(|_| Error::InvalidSourceDateEpoch(value))?,
+            Err(_) => 0,
+        };
+        Ok(TarMode::Reproducible { mtime })
+    }
+}
+
+pub fn tar_dir_to_file(
+    compiled_dir: &Path,
+    tar_file: &File,
+    mode: TarMode,
+    compression: Compression,
+) -> Result<(), Error> {
+    let enc = compression.encoder(tar_file).map_err(Error::FsError)?;
 
     let mut tar = tar::Builder::new(enc);
     // When set to true,  `follow_symlinks` will duplicate internal symlinks which increases the resulting file size
     tar.follow_symlinks(false);
-    tar.append_dir_all("", compiled_dir)
+    match mode {
+        TarMode::Preserve => tar
+            .append_dir_all("", compiled_dir)
+            .map_err(Error::FsError)?,
+        TarMode::Reproducible { mtime } => {
+            append_dir_reproducible(&mut tar, compiled_dir, mtime).map_err(Error::FsError)?
+        }
+    }
+    tar.into_inner()
+        .and_then(|enc| enc.finish())
         .map_err(Error::FsError)?;
-    tar.finish().map_err(Error::FsError)?;
 
     Ok(())
 }
 
+fn append_dir_reproducible<W: Write>(
+    tar: &mut tar::Builder<W>,
+    dir: &Path,
+    mtime: u64,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    for path in sorted_paths(dir)? {
+        let name = path.strip_prefix(dir).expect("walked from dir");
+        let metadata = fs::symlink_metadata(&path)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+
+        if metadata.is_symlink() {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            tar.append_link(&mut header, name, fs::read_link(&path)?)?;
+        } else if metadata.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            tar.append_data(&mut header, name, std::io::empty())?;
+        } else {
+            let executable = metadata.permissions().mode() & 0o111 != 0;
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(if executable { 0o755 } else { 0o644 });
+            header.set_size(metadata.len());
+            tar.append_data(&mut header, name, fs::File::open(&path)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Everything below `dir` (not `dir` itself), parents before children, siblings by name
+fn sorted_paths(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut children = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    children.sort();
+
+    let mut paths = Vec::new();
+    for child in children {
+        let is_dir = fs::symlink_metadata(&child)?.is_dir();
+        paths.push(child.clone());
+        if is_dir {
+            paths.extend(sorted_paths(&child)?);
+        }
+    }
+    Ok(paths)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BuildStatus {
     Success,
@@ -295,6 +619,113 @@ pub fn write_job_metadata(path: Option<&Path>, key: &str, value: &str) -> std::i
         .and_then(|mut file| writeln!(file, "{key}={value}"))
 }
 
+/// Rewrites the Ruby shebangs of scripts in `bin/` and gem executables below `dir` with
+/// [`update_shebang`], returning the rewritten files relative to `dir`
+pub fn update_shebangs_in_dir(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut updated = Vec::new();
+    for path in relocatable::scripts(dir).map_err(Error::FsError)? {
+        let Ok(contents) = String::from_utf8(fs::read(&path).map_err(Error::FsError)?) else {
+            continue;
+        };
+        if let Some(contents) = update_shebang(contents) {
+            fs::write(&path, contents).map_err(Error::FsError)?;
+            updated.push(path.strip_prefix(dir).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(updated)
+}
+
+/// Like [`update_shebangs_in_dir`] for the tarball at `tar_path`, replacing it when a script
+/// changed
+///
+/// Entries are copied header for header, so ownership, mtimes and the `./` prefix GNU tar writes
+/// are kept. Only rewritten scripts get a new size and checksum.
+pub fn update_shebangs_in_tar(
+    tar_path: &Path,
+    compression: Compression,
+) -> Result<Vec<PathBuf>, Error> {
+    use std::io::Read;
+
+    let dir = tar_path.parent().unwrap_or(Path::new("."));
+    let output = tempfile::NamedTempFile::new_in(dir).map_err(Error::FsError)?;
+    let file = fs::File::open(tar_path).map_err(Error::FsError)?;
+    let mut archive = tar::Archive::new(compression.decoder(file).map_err(Error::FsError)?);
+    let mut builder = tar::Builder::new(
+        compression
+            .encoder(output.as_file())
+            .map_err(Error::FsError)?,
+    );
+
+    let mut updated = Vec::new();
+    // Raw entries include GNU long name and PAX records, which are copied as they are and
+    // name the entry that follows them
+    let mut long_name: Option<PathBuf> = None;
+    for entry in archive.entries().map_err(Error::FsError)?.raw(true) {
+        let mut entry = entry.map_err(Error::FsError)?;
+        let mut header = entry.header().clone();
+        let kind = header.entry_type();
+        if kind.is_gnu_longname() || kind.is_pax_local_extensions() {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).map_err(Error::FsError)?;
+            long_name = if kind.is_gnu_longname() {
+                Some(PathBuf::from(
+                    String::from_utf8_lossy(&data)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                ))
+            } else {
+                tar::PaxExtensions::new(&data)
+                    .filter_map(Result::ok)
+                    .find(|extension| extension.key() == Ok("path"))
+                    .and_then(|extension| extension.value().ok().map(PathBuf::from))
+                    .or(long_name)
+            };
+            builder.append(&header, data.as_slice())
+        } else if kind.is_gnu_longlink() || kind.is_pax_global_extensions() {
+            builder.append(&header, &mut entry)
+        } else {
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => entry.path().map_err(Error::FsError)?.to_path_buf(),
+            };
+            let script = safe_relative_path(&name)
+                .filter(|relative| kind.is_file() && relocatable::is_script(relative));
+            if let Some(relative) = script {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).map_err(Error::FsError)?;
+                if let Some(contents) = String::from_utf8(data.clone())
+                    .ok()
+                    .and_then(update_shebang)
+                {
+                    data = contents.into_bytes();
+                    header.set_size(data.len() as u64);
+                    header.set_cksum();
+                    updated.push(relative);
+                }
+                builder.append(&header, data.as_slice())
+            } else {
+                builder.append(&header, &mut entry)
+            }
+        }
+        .map_err(Error::FsError)?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(Error::FsError)?;
+
+    if !updated.is_empty() {
+        let permissions = fs::metadata(tar_path)
+            .map_err(Error::FsError)?
+            .permissions();
+        fs::set_permissions(output.path(), permissions).map_err(Error::FsError)?;
+        output
+            .persist(tar_path)
+            .map_err(|error| Error::FsError(error.error))?;
+    }
+    Ok(updated)
+}
+
 pub fn update_shebang(contents: String) -> Option<String> {
     if let Some(shebang) = contents.lines().next() {
         if shebang.starts_with("#!") && shebang.contains("/ruby") {
@@ -341,6 +772,146 @@ mod test {
         assert_eq!(updated_contents, None);
     }
 
+    #[test]
+    fn test_update_shebangs_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &[u8]| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("bin/rake", b"#!/tmp/compiled/bin/ruby\nrequire 'rake'\n");
+        write("bin/ruby", b"\x7fELF\xff");
+        write("bin/irb", b"#!/usr/bin/env ruby\n");
+        write(
+            "lib/ruby/gems/3.4.0/gems/rbs-3.8.0/exe/rbs",
+            b"#!/tmp/compiled/bin/ruby\n",
+        );
+        write("lib/ruby/3.4.0/un.rb", b"#!/usr/local/bin/ruby\n");
+
+        let updated = update_shebangs_in_dir(dir.path()).unwrap();
+        assert_eq!(
+            updated,
+            vec![
+                PathBuf::from("bin/rake"),
+                PathBuf::from("lib/ruby/gems/3.4.0/gems/rbs-3.8.0/exe/rbs")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("bin/rake")).unwrap(),
+            "#!/usr/bin/env ruby\nrequire 'rake'\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib/ruby/3.4.0/un.rb")).unwrap(),
+            "#!/usr/local/bin/ruby\n"
+        );
+    }
+
+    #[test]
+    fn test_update_shebangs_in_tar_keeps_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let tar_path = dir.path().join("ruby-3.4.1.tgz");
+        let gem_exe = format!(
+            "./lib/ruby/gems/3.4.0/gems/{}-1.0.0/exe/tool",
+            "a".repeat(100)
+        );
+        {
+            let file = fs::File::create(&tar_path).unwrap();
+            let mut tar = tar::Builder::new(
+                Compression::Gzip
+                    .encoder(file.file().try_clone().unwrap())
+                    .unwrap(),
+            );
+            let header = |name: &str, size: usize| {
+                let mut header = tar::Header::new_gnu();
+                header.set_mtime(1_700_000_000);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_username("root").unwrap();
+                header.set_mode(0o755);
+                header.set_size(size as u64);
+                // Written directly, `set_path` would drop the `./` prefix
+                header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+                header.set_cksum();
+                header
+            };
+            for (name, contents) in [
+                (
+                    "./bin/rake",
+                    &b"#!/tmp/compiled/bin/ruby\nrequire 'rake'\n"[..],
+                ),
+                ("./bin/ruby", &b"\x7fELF\xff"[..]),
+            ] {
+                tar.append(&header(name, contents.len()), contents).unwrap();
+            }
+            let contents = b"#!/tmp/compiled/bin/ruby\n";
+            tar.append_data(&mut header("", contents.len()), &gem_exe, &contents[..])
+                .unwrap();
+            tar.into_inner().unwrap().finish().unwrap();
+        }
+
+        let updated = update_shebangs_in_tar(&tar_path, Compression::Gzip).unwrap();
+        assert_eq!(
+            updated,
+            vec![
+                PathBuf::from("bin/rake"),
+                PathBuf::from(gem_exe.trim_start_matches("./"))
+            ]
+        );
+
+        let file = fs::File::open(&tar_path).unwrap();
+        let mut archive = tar::Archive::new(Compression::Gzip.decoder(file).unwrap());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).unwrap();
+                let header = entry.header();
+                (
+                    String::from_utf8(entry.path_bytes().to_vec()).unwrap(),
+                    header.mtime().unwrap(),
+                    header.username().unwrap().map(String::from),
+                    contents,
+                )
+            })
+            .collect::<Vec<_>>();
+        let root = Some("root".to_string());
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "./bin/rake".to_string(),
+                    1_700_000_000,
+                    root.clone(),
+                    b"#!/usr/bin/env ruby\nrequire 'rake'\n".to_vec()
+                ),
+                (
+                    "./bin/ruby".to_string(),
+                    1_700_000_000,
+                    root.clone(),
+                    b"\x7fELF\xff".to_vec()
+                ),
+                (
+                    gem_exe.clone(),
+                    1_700_000_000,
+                    root,
+                    b"#!/usr/bin/env ruby\n".to_vec()
+                ),
+            ]
+        );
+
+        // Nothing left to rewrite, the tarball is left alone
+        let before = fs::read(&tar_path).unwrap();
+        assert!(
+            update_shebangs_in_tar(&tar_path, Compression::Gzip)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(fs::read(&tar_path).unwrap(), before);
+    }
+
     #[test]
     fn test_validate_version_for_stack() {
         assert!(
@@ -366,7 +937,8 @@ mod test {
         let version = RubyDownloadVersion::from_str("2.7.3").unwrap();
         let base_image = BaseImage::new("heroku-22").unwrap();
 
-        let tar_path = output_ruby_tar_path(&output, &version, &base_image, None);
+        let tar_path =
+            output_ruby_tar_path(&output, &version, &base_image, None, Compression::Gzip);
 
         // assert!(tar_path.is_absolute());
         assert_eq!(PathBuf::from("/tmp/heroku-22/ruby-2.7.3.tgz"), tar_path);
@@ -379,8 +951,13 @@ mod test {
         let base_image = BaseImage::new("heroku-24").unwrap();
         let cpu_architecture = Arch::Amd64;
 
-        let tar_path =
-            output_ruby_tar_path(&output, &version, &base_image, Some(&cpu_architecture));
+        let tar_path = output_ruby_tar_path(
+            &output,
+            &version,
+            &base_image,
+            Some(&cpu_architecture),
+            Compression::Gzip,
+        );
 
         assert_eq!(
             PathBuf::from("/tmp/heroku-24/amd64/ruby-2.7.3.tgz"),
@@ -388,10 +965,19 @@ mod test {
         );
     }
 
+    #[derive(Debug, PartialEq)]
+    struct Transient(&'static str);
+
+    impl Classify for Transient {
+        fn classify(&self) -> ErrorClass {
+            ErrorClass::TRANSIENT
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_with_retries_succeeds_first_attempt() {
         let attempts = AtomicUsize::new(0);
-        let result: Result<&str, &str> = with_retries(|| {
+        let result: Result<&str, Transient> = with_retries(|| {
             attempts.fetch_add(1, Ordering::SeqCst);
             async { Ok("ok") }
         })
@@ -404,11 +990,11 @@ mod test {
     #[tokio::test(flavor = "multi_thread")]
     async fn test_with_retries_succeeds_after_transient_failures() {
         let attempts = AtomicUsize::new(0);
-        let result: Result<&str, &str> = with_retries(|| {
+        let result: Result<&str, Transient> = with_retries(|| {
             let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
             async move {
                 if attempt < MAX_RETRY_ATTEMPTS as usize {
-                    Err("transient")
+                    Err(Transient("transient"))
                 } else {
                     Ok("ok")
                 }
@@ -423,16 +1009,21 @@ mod test {
     #[tokio::test(flavor = "multi_thread")]
     async fn test_with_retries_gives_up_after_max_attempts() {
         let attempts = AtomicUsize::new(0);
-        let result: Result<&str, &str> = with_retries(|| {
+        let result: Result<&str, Transient> = with_retries(|| {
             attempts.fetch_add(1, Ordering::SeqCst);
-            async { Err("always fails") }
+            async { Err(Transient("always fails")) }
         })
         .await;
 
-        assert_eq!(result, Err("always fails"));
+        assert_eq!(result, Err(Transient("always fails")));
         assert_eq!(attempts.load(Ordering::SeqCst), MAX_RETRY_ATTEMPTS as usize);
     }
 
+    /// A client owned by one test's runtime, unlike the process-wide [`http::client`]
+    fn test_client() -> reqwest::Client {
+        http::HttpConfig::default().build().unwrap()
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_download_tar() {
         let server = Server::http("127.0.0.1:0").unwrap();
@@ -446,7 +1037,9 @@ mod test {
         let dir = tempdir().unwrap();
         let tar_path = TarDownloadPath(dir.path().join("file.tar"));
 
-        download_tar(&addr, &tar_path).await.unwrap();
+        download_tar(&test_client(), &addr, &tar_path)
+            .await
+            .unwrap();
 
         let mut file = fs::File::open(tar_path.as_ref()).unwrap();
         let mut contents = String::new();
@@ -468,11 +1061,39 @@ mod test {
         let dir = tempdir().unwrap();
         let tar_path = TarDownloadPath(dir.path().join("file.tar"));
 
-        let result = download_tar(&addr, &tar_path).await;
+        let result = download_tar(&test_client(), &addr, &tar_path).await;
 
         assert!(result.is_err());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_tar_retries_server_errors() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", server.server_addr());
+
+        thread::spawn(move || {
+            for (index, request) in server.incoming_requests().enumerate() {
+                let _ = if index == 0 {
+                    request.respond(Response::empty(tiny_http::StatusCode(503)))
+                } else {
+                    request.respond(Response::from_string("Hello, world!"))
+                };
+            }
+        });
+
+        let dir = tempdir().unwrap();
+        let tar_path = TarDownloadPath(dir.path().join("file.tar"));
+
+        download_tar(&test_client(), &addr, &tar_path)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(tar_path.as_ref()).unwrap(),
+            "Hello, world!"
+        );
+    }
+
     #[test]
     fn test_ruby_version_bundler_format() {
         assert_eq!(
@@ -545,7 +1166,13 @@ mod test {
         let temptar_dir = tempfile::tempdir().unwrap();
         let tar_path = temptar_dir.path().join("ruby-source-3.3.1.tgz");
 
-        tar_dir_to_file(tempdir.path(), &fs::File::create(&tar_path).unwrap()).unwrap();
+        tar_dir_to_file(
+            tempdir.path(),
+            &fs::File::create(&tar_path).unwrap(),
+            TarMode::Preserve,
+            Compression::Gzip,
+        )
+        .unwrap();
 
         let temp_out = tempfile::tempdir().unwrap();
         untar_to_dir(&TarDownloadPath(tar_path), temp_out.path()).unwrap();
@@ -557,6 +1184,221 @@ mod test {
         assert!(filenames.iter().any(|name| *name == "array.c"));
     }
 
+    /// Write a gzipped tarball of raw entries, bypassing the path checks `tar::Builder` makes
+    /// so malicious archives can be built
+    fn fixture_tgz(path: &Path, entries: &[(tar::EntryType, &str, Option<&str>, u32, &str)]) {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (entry_type, name, link, mode, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(*entry_type);
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.name[..name.len()].copy_from_slice(name.as_bytes());
+            if let Some(link) = link {
+                gnu.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            }
+            header.set_mode(*mode);
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        let mut enc = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        enc.write_all(&builder.into_inner().unwrap()).unwrap();
+        enc.finish().unwrap();
+    }
+
+    #[test]
+    fn test_untar_to_dir_links_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        use tar::EntryType::{Directory, Link, Regular, Symlink, XGlobalHeader};
+
+        let tempdir = tempfile::tempdir().unwrap();
+        // Spaces and shell metacharacters in paths used to break the `bash -c` version
+        let tar_path = tempdir.path().join("my dir; $(echo) jruby.tgz");
+        fixture_tgz(
+            &tar_path,
+            &[
+                (XGlobalHeader, "pax_global_header", None, 0o666, ""),
+                (Directory, "./jruby/", None, 0o755, ""),
+                (Directory, "./jruby/bin/", None, 0o755, ""),
+                (Regular, "./jruby/bin/jruby", None, 0o755, "#!/bin/sh\n"),
+                (Regular, "./jruby/COPYING", None, 0o600, "license"),
+                (Symlink, "./jruby/bin/ruby", Some("jruby"), 0o777, ""),
+                // A hard link to a symlink, https://github.com/alexcrichton/tar-rs/issues/369
+                (
+                    Link,
+                    "./jruby/bin/rake",
+                    Some("./jruby/bin/ruby"),
+                    0o777,
+                    "",
+                ),
+                (Directory, "./jruby/readonly/", None, 0o555, ""),
+                // A later directory replaces a file
+                (Regular, "./jruby/lib", None, 0o644, "not a dir"),
+                (Directory, "./jruby/lib/", None, 0o755, ""),
+            ],
+        );
+        let out = tempdir.path().join("out dir");
+        untar_to_dir(&TarDownloadPath(tar_path), &out).unwrap();
+
+        let bin = out.join("jruby").join("bin");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(
+            fs::read_to_string(bin.join("jruby")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        assert_eq!(mode(&bin.join("jruby")), 0o755);
+        assert_eq!(mode(&out.join("jruby").join("COPYING")), 0o600);
+        assert_eq!(mode(&out.join("jruby").join("readonly")), 0o555);
+        assert_eq!(
+            fs::read_link(bin.join("ruby")).unwrap(),
+            PathBuf::from("jruby")
+        );
+        assert_eq!(fs::read_to_string(bin.join("rake")).unwrap(), "#!/bin/sh\n");
+        assert!(out.join("jruby").join("lib").is_dir());
+        assert!(!out.join("pax_global_header").exists());
+    }
+
+    #[test]
+    fn test_untar_to_dir_rejects_traversal() {
+        use std::os::unix::fs::PermissionsExt;
+        use tar::EntryType::{Directory, Link, Regular, Symlink};
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let outside = tempdir.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::set_permissions(&outside, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        for (name, entries) in [
+            ("dotdot", vec![(Regular, "../evil", None, 0o644, "x")]),
+            ("absolute", vec![(Regular, "/tmp/evil", None, 0o644, "x")]),
+            (
+                "through symlink",
+                vec![
+                    (Symlink, "link", Some(outside.to_str().unwrap()), 0o777, ""),
+                    (Regular, "link/evil", None, 0o644, "x"),
+                ],
+            ),
+            (
+                "directory over symlink",
+                vec![
+                    (Symlink, "d", Some(outside.to_str().unwrap()), 0o777, ""),
+                    (Directory, "d", None, 0o777, ""),
+                ],
+            ),
+            (
+                "hard link through symlink",
+                vec![
+                    (Symlink, "a", Some("/etc"), 0o777, ""),
+                    (Link, "evil", Some("a/passwd"), 0o644, ""),
+                ],
+            ),
+            (
+                "hard link to missing file",
+                vec![(Link, "evil", Some("missing"), 0o644, "")],
+            ),
+        ] {
+            let tar_path = tempdir.path().join(format!("{name}.tgz"));
+            fixture_tgz(&tar_path, &entries);
+            let out = tempdir.path().join(name).join("out");
+
+            let result = untar_to_dir(&TarDownloadPath(tar_path), &out);
+            assert!(
+                matches!(result, Err(Error::UnsafeTarEntry { .. })),
+                "{name}: {result:?}"
+            );
+            assert!(!tempdir.path().join(name).join("evil").exists(), "{name}");
+            assert!(!outside.join("evil").exists(), "{name}");
+            assert!(!out.join("evil").exists(), "{name}");
+            assert_eq!(
+                fs::metadata(&outside).unwrap().permissions().mode() & 0o7777,
+                0o755,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tar_dir_to_file_reproducible() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Same contents written in a different order, with different mtimes and permissions
+        let build = |names: &[&str], mode: u32, mtime: u64| {
+            let dir = tempfile::tempdir().unwrap();
+            for name in names {
+                let path = dir.path().join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, format!("contents of {name}")).unwrap();
+                let mode = if name.starts_with("bin/") {
+                    mode
+                } else {
+                    mode & 0o666
+                };
+                fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+                fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(std::time::UNIX_EPOCH + Duration::from_secs(mtime))
+                    .unwrap();
+            }
+            fs::os::unix::fs::symlink("jruby", dir.path().join("bin").join("ruby")).unwrap();
+            dir
+        };
+        let tar = |dir: &Path, compression: Compression| {
+            let out = tempfile::tempdir().unwrap();
+            let path = out.path().join("out");
+            tar_dir_to_file(
+                dir,
+                &fs::File::create(&path).unwrap(),
+                TarMode::Reproducible {
+                    mtime: 1_700_000_000,
+                },
+                compression,
+            )
+            .unwrap();
+            fs::read(&path).unwrap()
+        };
+
+        let one = build(&["bin/jruby", "lib/jruby.jar", "COPYING"], 0o700, 1);
+        let two = build(&["COPYING", "lib/jruby.jar", "bin/jruby"], 0o750, 2);
+        for compression in Compression::ALL {
+            let first = tar(one.path(), compression);
+            assert_eq!(first, tar(two.path(), compression), "{compression}");
+            assert_eq!(first, tar(one.path(), compression), "{compression}");
+        }
+        let first = tar(one.path(), Compression::Gzip);
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(first.as_slice()));
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let header = entry.header();
+                (
+                    entry.path().unwrap().display().to_string(),
+                    header.mode().unwrap(),
+                    header.mtime().unwrap(),
+                    header.uid().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("COPYING".to_string(), 0o644, 1_700_000_000, 0),
+                ("bin".to_string(), 0o755, 1_700_000_000, 0),
+                ("bin/jruby".to_string(), 0o755, 1_700_000_000, 0),
+                ("bin/ruby".to_string(), 0o777, 1_700_000_000, 0),
+                ("lib".to_string(), 0o755, 1_700_000_000, 0),
+                ("lib/jruby.jar".to_string(), 0o644, 1_700_000_000, 0),
+            ]
+        );
+    }
+
     #[test]
     fn test_build_status_as_str() {
         assert_eq!(BuildStatus::Success.as_str(), "success");
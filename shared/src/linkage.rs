@@ -0,0 +1,308 @@
+//! Shared library linkage of an extracted Ruby
+//!
+//! Ruby is built with `--enable-shared` against the `-dev` packages of the build image, but runs
+//! on the slimmer run image. [`LDD_SCRIPT`] runs `ldd` over `bin/ruby`, `libruby.so` and every
+//! extension in the run image, and [`LinkageReport::parse`] reads its output so missing
+//! libraries fail the check instead of a production boot.
+
+use crate::summary::Table;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+/// Runs `ldd` on each ELF file Ruby loads, run from the root of the extracted Ruby
+///
+/// Each file's output is preceded by a `==> <path>` line. Extensions under `lib/ruby` have no
+/// `RUNPATH`, at runtime they get `libruby` from the `ruby` process that loads them, so `ldd`
+/// looks for it in the extracted `lib` (`bin/ruby` still has to find it through its own
+/// `RUNPATH`).
+pub const LDD_SCRIPT: &str = r#"find bin lib -type f \( -name ruby -o -name '*.so' -o -name '*.so.*' \) | sort | while read -r file; do echo "==> $file"; case "$file" in lib/ruby/*) LD_LIBRARY_PATH="$PWD/lib" ldd "$file" 2>&1 ;; *) ldd "$file" 2>&1 ;; esac || true; done"#;
+
+/// Directories the run image's own libraries live in
+const SYSTEM_LIBRARY_DIRS: [&str; 4] = ["/lib", "/lib64", "/usr/lib", "/usr/lib64"];
+
+/// A library an ELF file needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    pub name: String,
+    /// `None` when `ldd` reports "not found"
+    pub resolved: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLinkage {
+    /// Relative to the root of the extracted Ruby
+    pub path: String,
+    pub libraries: Vec<Library>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkageProblem {
+    /// Fails the check
+    NotFound { file: String, library: String },
+    /// Resolved outside both the Ruby under test and the system library directories, or
+    /// `libruby` resolved to a different Ruby
+    Unexpected {
+        file: String,
+        library: String,
+        path: PathBuf,
+    },
+}
+
+impl Display for LinkageProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkageProblem::NotFound { file, library } => write!(f, "{file}: {library} not found"),
+            LinkageProblem::Unexpected {
+                file,
+                library,
+                path,
+            } => write!(
+                f,
+                "{file}: {library} unexpectedly resolved to {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkageReport {
+    pub files: Vec<FileLinkage>,
+}
+
+impl LinkageReport {
+    /// Parse the output of [`LDD_SCRIPT`]
+    pub fn parse(output: &str) -> Self {
+        let mut files = Vec::<FileLinkage>::new();
+        for line in output.lines() {
+            if let Some(path) = line.strip_prefix("==> ") {
+                files.push(FileLinkage {
+                    path: path.trim().to_string(),
+                    libraries: Vec::new(),
+                });
+            } else if let (Some(file), Some(library)) = (files.last_mut(), parse_ldd_line(line)) {
+                file.libraries.push(library);
+            }
+        }
+        Self { files }
+    }
+
+    /// Problems for a Ruby mounted at `ruby_dir` when `ldd` ran
+    pub fn problems(&self, ruby_dir: &Path) -> Vec<LinkageProblem> {
+        self.files
+            .iter()
+            .flat_map(|file| {
+                file.libraries.iter().filter_map(|library| {
+                    let Some(path) = &library.resolved else {
+                        return Some(LinkageProblem::NotFound {
+                            file: file.path.clone(),
+                            library: library.name.clone(),
+                        });
+                    };
+                    let expected = if library.name.starts_with("libruby") {
+                        path.starts_with(ruby_dir)
+                    } else {
+                        path.starts_with(ruby_dir)
+                            || SYSTEM_LIBRARY_DIRS.iter().any(|dir| path.starts_with(dir))
+                    };
+                    (!expected).then(|| LinkageProblem::Unexpected {
+                        file: file.path.clone(),
+                        library: library.name.clone(),
+                        path: path.clone(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_markdown(&self, ruby_dir: &Path, image: &str) -> String {
+        let problems = self.problems(ruby_dir);
+        if problems.is_empty() {
+            return format!(
+                "### Linkage in {image}\n\n✅ {} files, all libraries found\n",
+                self.files.len()
+            );
+        }
+        let mut table = Table::new(["Problem", "File", "Library", "Resolved to"].map(String::from));
+        for problem in &problems {
+            table.row(match problem {
+                LinkageProblem::NotFound { file, library } => [
+                    "❌ not found".to_string(),
+                    format!("`{file}`"),
+                    library.clone(),
+                    "-".to_string(),
+                ],
+                LinkageProblem::Unexpected {
+                    file,
+                    library,
+                    path,
+                } => [
+                    "⚠️ unexpected".to_string(),
+                    format!("`{file}`"),
+                    library.clone(),
+                    format!("`{}`", path.display()),
+                ],
+            });
+        }
+        format!("### Linkage in {image}\n\n{table}")
+    }
+}
+
+/// One line of `ldd` output, `None` for the vDSO and anything that isn't a library
+fn parse_ldd_line(line: &str) -> Option<Library> {
+    let line = line.trim();
+    let without_address = |text: &str| {
+        text.rsplit_once(" (0x")
+            .map_or(text, |(before, _)| before)
+            .trim()
+            .to_string()
+    };
+    match line.split_once(" => ") {
+        Some((name, "not found")) => Some(Library {
+            name: name.trim().to_string(),
+            resolved: None,
+        }),
+        Some((name, path)) => Some(Library {
+            name: name.trim().to_string(),
+            resolved: Some(PathBuf::from(without_address(path))),
+        }),
+        // The dynamic loader is listed by its absolute path
+        None if line.starts_with('/') => {
+            let path = without_address(line);
+            Some(Library {
+                name: Path::new(&path)
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                resolved: Some(PathBuf::from(path)),
+            })
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `LDD_SCRIPT` output for Ruby 3.4.1 on heroku-24 (abridged to a few extensions)
+    const OUTPUT: &str = "==> bin/ruby
+\tlinux-vdso.so.1 (0x00007ffd3a9e4000)
+\tlibruby.so.3.4 => /tmp/.tmpq3Xv9L/bin/../lib/libruby.so.3.4 (0x00007f5b8d800000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f5b8d400000)
+\tlibz.so.1 => /lib/x86_64-linux-gnu/libz.so.1 (0x00007f5b8de2b000)
+\tlibgmp.so.10 => /lib/x86_64-linux-gnu/libgmp.so.10 (0x00007f5b8dda4000)
+\tlibcrypt.so.1 => /lib/x86_64-linux-gnu/libcrypt.so.1 (0x00007f5b8dd6a000)
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f5b8d717000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f5b8de5d000)
+==> lib/libruby.so.3.4.1
+\tlinux-vdso.so.1 (0x00007ffe1c3f2000)
+\tlibz.so.1 => /lib/x86_64-linux-gnu/libz.so.1 (0x00007f2a4c7a1000)
+\tlibgmp.so.10 => /lib/x86_64-linux-gnu/libgmp.so.10 (0x00007f2a4c71a000)
+\tlibcrypt.so.1 => /lib/x86_64-linux-gnu/libcrypt.so.1 (0x00007f2a4c6e0000)
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f2a4c5f7000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f2a4be00000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f2a4c7d3000)
+==> lib/ruby/3.4.0/x86_64-linux/openssl.so
+\tlinux-vdso.so.1 (0x00007ffc5d1e8000)
+\tlibruby.so.3.4 => /tmp/.tmpq3Xv9L/lib/libruby.so.3.4 (0x00007f8e3b800000)
+\tlibssl.so.3 => /lib/x86_64-linux-gnu/libssl.so.3 (0x00007f8e3bd2f000)
+\tlibcrypto.so.3 => /lib/x86_64-linux-gnu/libcrypto.so.3 (0x00007f8e3b200000)
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f8e3bc46000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f8e3ae00000)
+\tlibz.so.1 => /lib/x86_64-linux-gnu/libz.so.1 (0x00007f8e3bc2a000)
+\tlibgmp.so.10 => /lib/x86_64-linux-gnu/libgmp.so.10 (0x00007f8e3b77b000)
+\tlibcrypt.so.1 => /lib/x86_64-linux-gnu/libcrypt.so.1 (0x00007f8e3b741000)
+\tlibzstd.so.1 => /lib/x86_64-linux-gnu/libzstd.so.1 (0x00007f8e3b687000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f8e3bdf6000)
+==> lib/ruby/3.4.0/x86_64-linux/zlib.so
+\tlinux-vdso.so.1 (0x00007fff0b5c6000)
+\tlibruby.so.3.4 => /tmp/.tmpq3Xv9L/lib/libruby.so.3.4 (0x00007f0c6e800000)
+\tlibz.so.1 => /lib/x86_64-linux-gnu/libz.so.1 (0x00007f0c6ee3a000)
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f0c6ed51000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f0c6e400000)
+\tlibgmp.so.10 => /lib/x86_64-linux-gnu/libgmp.so.10 (0x00007f0c6ecca000)
+\tlibcrypt.so.1 => /lib/x86_64-linux-gnu/libcrypt.so.1 (0x00007f0c6ec90000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f0c6ee6c000)
+";
+
+    /// An extension whose libraries the run image doesn't ship, one of them picked up from
+    /// `/usr/local` instead
+    const BROKEN_OUTPUT: &str = "==> lib/ruby/3.4.0/x86_64-linux/psych.so
+\tlinux-vdso.so.1 (0x00007ffd81b7c000)
+\tlibruby.so.3.4 => /tmp/.tmpq3Xv9L/lib/libruby.so.3.4 (0x00007f41c7a00000)
+\tlibyaml-0.so.2 => not found
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f41c7f17000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f41c7600000)
+\tlibz.so.1 => /lib/x86_64-linux-gnu/libz.so.1 (0x00007f41c7efb000)
+\tlibgmp.so.10 => /usr/local/lib/libgmp.so.10 (0x00007f41c7e74000)
+\tlibcrypt.so.1 => /lib/x86_64-linux-gnu/libcrypt.so.1 (0x00007f41c7e3a000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f41c8024000)
+==> lib/ruby/3.4.0/x86_64-linux/fiddle.so
+\tlinux-vdso.so.1 (0x00007ffe9a1f3000)
+\tlibruby.so.3.4 => /usr/lib/x86_64-linux-gnu/libruby-3.4.so.3.4 (0x00007fb2d9a00000)
+\tlibffi.so.8 => /lib/x86_64-linux-gnu/libffi.so.8 (0x00007fb2da0f1000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007fb2d9600000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007fb2da12e000)
+";
+
+    #[test]
+    fn parses_ldd_output() {
+        let report = LinkageReport::parse(OUTPUT);
+        assert_eq!(
+            report
+                .files
+                .iter()
+                .map(|file| (file.path.as_str(), file.libraries.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bin/ruby", 7),
+                ("lib/libruby.so.3.4.1", 6),
+                ("lib/ruby/3.4.0/x86_64-linux/openssl.so", 10),
+                ("lib/ruby/3.4.0/x86_64-linux/zlib.so", 7),
+            ]
+        );
+        assert_eq!(
+            report.files[0].libraries[0],
+            Library {
+                name: "libruby.so.3.4".to_string(),
+                resolved: Some(PathBuf::from("/tmp/.tmpq3Xv9L/bin/../lib/libruby.so.3.4")),
+            }
+        );
+        assert_eq!(
+            report.files[0].libraries[6],
+            Library {
+                name: "ld-linux-x86-64.so.2".to_string(),
+                resolved: Some(PathBuf::from("/lib64/ld-linux-x86-64.so.2")),
+            }
+        );
+        assert_eq!(report.problems(Path::new("/tmp/.tmpq3Xv9L")), Vec::new());
+    }
+
+    #[test]
+    fn reports_missing_and_unexpected_libraries() {
+        let report = LinkageReport::parse(BROKEN_OUTPUT);
+        let ruby_dir = Path::new("/tmp/.tmpq3Xv9L");
+        assert_eq!(
+            report
+                .problems(ruby_dir)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "lib/ruby/3.4.0/x86_64-linux/psych.so: libyaml-0.so.2 not found",
+                "lib/ruby/3.4.0/x86_64-linux/psych.so: libgmp.so.10 unexpectedly resolved to /usr/local/lib/libgmp.so.10",
+                "lib/ruby/3.4.0/x86_64-linux/fiddle.so: libruby.so.3.4 unexpectedly resolved to /usr/lib/x86_64-linux-gnu/libruby-3.4.so.3.4",
+            ]
+        );
+        assert!(report.to_markdown(ruby_dir, "heroku/heroku:24").contains(
+            "| ❌ not found | `lib/ruby/3.4.0/x86_64-linux/psych.so` | libyaml-0.so.2 | - |"
+        ));
+        assert!(
+            LinkageReport::default()
+                .to_markdown(ruby_dir, "heroku/heroku:24")
+                .contains("✅ 0 files")
+        );
+    }
+}
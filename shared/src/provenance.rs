@@ -0,0 +1,430 @@
+//! Build provenance for the tarballs we produce
+//!
+//! Every `.tgz` written to the output directory gets an [in-toto statement] next to it, named
+//! `<tarball>.intoto.json`, carrying a [SLSA provenance] predicate: the source that went in (URL
+//! and checksum), how it was built (Dockerfile digest, `./configure` options, base image,
+//! architecture) and which commit of this repository did the building. [`verify`] checks a
+//! tarball against its statement.
+//!
+//! [in-toto statement]: https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md
+//! [SLSA provenance]: https://slsa.dev/spec/v1.0/provenance
+
+use crate::sha256_from_path;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+pub const BUILD_TYPE: &str = "https://github.com/heroku/docker-heroku-ruby-builder/build@v1";
+const REPOSITORY_URL: &str = "https://github.com/heroku/docker-heroku-ruby-builder";
+
+/// Where the statement for `tarball` is written
+pub fn provenance_path(tarball: &Path) -> PathBuf {
+    let mut name = tarball.file_name().unwrap_or_default().to_os_string();
+    name.push(".intoto.json");
+    tarball.with_file_name(name)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProvenanceError {
+    #[error("Cannot read {path}: {source}")]
+    CannotRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Cannot write {path}: {source}")]
+    CannotWrite {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid provenance {path}: {source}")]
+    InvalidJson {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Unsupported provenance {path}, expected `{expected}` but got `{actual}`")]
+    UnsupportedType {
+        path: PathBuf,
+        expected: &'static str,
+        actual: String,
+    },
+
+    #[error("Cannot compute sha256 of {path}: {source}")]
+    CannotDigest {
+        path: PathBuf,
+        #[source]
+        source: Box<crate::Error>,
+    },
+
+    #[error("{name} is not a subject of the provenance, subjects: {subjects}")]
+    NoSubject { name: String, subjects: String },
+
+    #[error("{name} does not match its provenance, expected sha256 {expected} but got {actual}")]
+    DigestMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// A content address, keyed by algorithm (only `sha256` here)
+pub type Digest = BTreeMap<String, String>;
+
+fn sha256_digest(sha256: impl Into<String>) -> Digest {
+    Digest::from([("sha256".to_string(), sha256.into())])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subject {
+    pub name: String,
+    pub digest: Digest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceDescriptor {
+    pub uri: String,
+    pub digest: Digest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    pub external_parameters: serde_json::Value,
+    pub internal_parameters: serde_json::Value,
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Builder {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    pub invocation_id: Option<String>,
+    pub finished_on: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunDetails {
+    pub builder: Builder,
+    pub metadata: BuildMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Predicate {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+/// An in-toto statement with a SLSA provenance predicate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<Subject>,
+    pub predicate_type: String,
+    pub predicate: Predicate,
+}
+
+/// What went into a build, recorded in every statement it produces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInputs {
+    pub version: String,
+    pub base_image: String,
+    /// `None` for architecture independent builds
+    pub arch: Option<String>,
+    pub source_url: String,
+    pub source_sha256: String,
+    /// `None` for builds that don't run in Docker
+    pub dockerfile_sha256: Option<String>,
+    pub configure_options: Vec<String>,
+    /// Commit of this repository that ran the build, see [`builder_commit`]
+    pub builder_commit: Option<String>,
+}
+
+impl BuildInputs {
+    /// A statement for the tarball(s) with the given names, all with the same contents
+    pub fn statement(&self, names: &[String], sha256: &str) -> Statement {
+        let mut resolved_dependencies = vec![ResourceDescriptor {
+            uri: self.source_url.clone(),
+            digest: sha256_digest(&self.source_sha256),
+        }];
+        if let Some(commit) = &self.builder_commit {
+            resolved_dependencies.push(ResourceDescriptor {
+                uri: format!("git+{REPOSITORY_URL}"),
+                digest: Digest::from([("gitCommit".to_string(), commit.clone())]),
+            });
+        }
+
+        Statement {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: names
+                .iter()
+                .map(|name| Subject {
+                    name: name.clone(),
+                    digest: sha256_digest(sha256),
+                })
+                .collect(),
+            predicate_type: PREDICATE_TYPE.to_string(),
+            predicate: Predicate {
+                build_definition: BuildDefinition {
+                    build_type: BUILD_TYPE.to_string(),
+                    external_parameters: serde_json::json!({
+                        "version": self.version,
+                        "base_image": self.base_image,
+                        "arch": self.arch,
+                    }),
+                    internal_parameters: serde_json::json!({
+                        "dockerfile_sha256": self.dockerfile_sha256,
+                        "configure_options": self.configure_options,
+                    }),
+                    resolved_dependencies,
+                },
+                run_details: RunDetails {
+                    builder: Builder { id: builder_id() },
+                    metadata: BuildMetadata {
+                        invocation_id: invocation_id(),
+                        finished_on: chrono::Utc::now()
+                            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    },
+                },
+            },
+        }
+    }
+
+    /// Write a statement next to `tarball` covering it and any `aliases` (copies with the same
+    /// contents, such as the SHA suffixed file), returning the statement's path
+    pub fn write_for(
+        &self,
+        tarball: &Path,
+        sha256: &str,
+        aliases: &[&Path],
+    ) -> Result<PathBuf, ProvenanceError> {
+        let names = std::iter::once(tarball)
+            .chain(aliases.iter().copied())
+            .map(file_name)
+            .collect::<Vec<_>>();
+        let path = provenance_path(tarball);
+        let json = serde_json::to_string_pretty(&self.statement(&names, sha256))
+            .expect("statement serializes to JSON");
+        fs::write(&path, json).map_err(|source| ProvenanceError::CannotWrite {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(path)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The workflow that ran the build on GitHub Actions, otherwise `local`
+fn builder_id() -> String {
+    match (
+        std::env::var("GITHUB_SERVER_URL"),
+        std::env::var("GITHUB_WORKFLOW_REF"),
+    ) {
+        (Ok(server), Ok(workflow_ref)) => format!("{server}/{workflow_ref}"),
+        _ => "local".to_string(),
+    }
+}
+
+fn invocation_id() -> Option<String> {
+    let server = std::env::var("GITHUB_SERVER_URL").ok()?;
+    let repository = std::env::var("GITHUB_REPOSITORY").ok()?;
+    let run_id = std::env::var("GITHUB_RUN_ID").ok()?;
+    Some(format!("{server}/{repository}/actions/runs/{run_id}"))
+}
+
+/// Commit of this repository doing the build, from `$GITHUB_SHA` or `git rev-parse HEAD`
+pub fn builder_commit() -> Option<String> {
+    std::env::var("GITHUB_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(crate::source_dir())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    })
+}
+
+/// The `./configure` flags in the `configure_opts=( ... )` array of `make_ruby.sh`
+pub fn configure_options(script: &str) -> Vec<String> {
+    script
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| !line.starts_with("configure_opts=("))
+        .skip(1)
+        .take_while(|line| !line.starts_with(')'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Check `tarball` against the statement at `provenance`
+///
+/// The tarball's file name must be one of the statement's subjects and its sha256 must match.
+pub fn verify(tarball: &Path, provenance: &Path) -> Result<Statement, ProvenanceError> {
+    let contents =
+        fs::read_to_string(provenance).map_err(|source| ProvenanceError::CannotRead {
+            path: provenance.to_path_buf(),
+            source,
+        })?;
+    let statement: Statement =
+        serde_json::from_str(&contents).map_err(|source| ProvenanceError::InvalidJson {
+            path: provenance.to_path_buf(),
+            source,
+        })?;
+    for (expected, actual) in [
+        (STATEMENT_TYPE, &statement.statement_type),
+        (PREDICATE_TYPE, &statement.predicate_type),
+    ] {
+        if expected != actual {
+            return Err(ProvenanceError::UnsupportedType {
+                path: provenance.to_path_buf(),
+                expected,
+                actual: actual.clone(),
+            });
+        }
+    }
+
+    let name = file_name(tarball);
+    let subject = statement
+        .subject
+        .iter()
+        .find(|subject| subject.name == name)
+        .ok_or_else(|| ProvenanceError::NoSubject {
+            name: name.clone(),
+            subjects: statement
+                .subject
+                .iter()
+                .map(|subject| subject.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        })?;
+    let expected = subject.digest.get("sha256").cloned().unwrap_or_default();
+    let actual = sha256_from_path(tarball).map_err(|source| ProvenanceError::CannotDigest {
+        path: tarball.to_path_buf(),
+        source: Box::new(source),
+    })?;
+    if expected != actual {
+        return Err(ProvenanceError::DigestMismatch {
+            name,
+            expected,
+            actual,
+        });
+    }
+
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> BuildInputs {
+        BuildInputs {
+            version: "3.4.1".to_string(),
+            base_image: "heroku-24".to_string(),
+            arch: Some("amd64".to_string()),
+            source_url: "https://cache.ruby-lang.org/pub/ruby/3.4/ruby-3.4.1.tar.gz".to_string(),
+            source_sha256: "0123".to_string(),
+            dockerfile_sha256: Some("4567".to_string()),
+            configure_options: vec!["--enable-shared".to_string()],
+            builder_commit: Some("89ab".to_string()),
+        }
+    }
+
+    #[test]
+    fn configure_options_from_make_ruby() {
+        let script = fs::read_to_string(crate::source_dir().join("make_ruby.sh")).unwrap();
+        let options = configure_options(&script);
+
+        assert!(
+            options.contains(&"--enable-shared".to_string()),
+            "got: {options:?}"
+        );
+        assert!(options.iter().all(|option| option.starts_with("--")));
+    }
+
+    #[test]
+    fn test_provenance_path() {
+        assert_eq!(
+            provenance_path(Path::new("output/heroku-24/amd64/ruby-3.4.1.tgz")),
+            PathBuf::from("output/heroku-24/amd64/ruby-3.4.1.tgz.intoto.json")
+        );
+    }
+
+    #[test]
+    fn statement_records_inputs() {
+        let statement = inputs().statement(&["ruby-3.4.1.tgz".to_string()], "cdef");
+        let json = serde_json::to_value(&statement).unwrap();
+
+        assert_eq!(json["_type"], STATEMENT_TYPE);
+        assert_eq!(json["predicateType"], PREDICATE_TYPE);
+        assert_eq!(json["subject"][0]["name"], "ruby-3.4.1.tgz");
+        assert_eq!(json["subject"][0]["digest"]["sha256"], "cdef");
+        let definition = &json["predicate"]["buildDefinition"];
+        assert_eq!(definition["externalParameters"]["arch"], "amd64");
+        assert_eq!(
+            definition["internalParameters"]["dockerfile_sha256"],
+            "4567"
+        );
+        assert_eq!(
+            definition["resolvedDependencies"][0]["digest"]["sha256"],
+            "0123"
+        );
+        assert_eq!(
+            definition["resolvedDependencies"][1]["digest"]["gitCommit"],
+            "89ab"
+        );
+    }
+
+    #[test]
+    fn verify_checks_name_and_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let tarball = dir.path().join("ruby-3.4.1.tgz");
+        let alias = dir.path().join("ruby-3.4.1-abcdef0.tgz");
+        fs::write(&tarball, "tarball contents").unwrap();
+        fs::write(&alias, "tarball contents").unwrap();
+        let sha256 = sha256_from_path(&tarball).unwrap();
+
+        let provenance = inputs().write_for(&tarball, &sha256, &[&alias]).unwrap();
+        assert_eq!(provenance, provenance_path(&tarball));
+
+        verify(&tarball, &provenance).unwrap();
+        verify(&alias, &provenance).unwrap();
+
+        fs::write(&tarball, "tampered").unwrap();
+        assert!(matches!(
+            verify(&tarball, &provenance),
+            Err(ProvenanceError::DigestMismatch { .. })
+        ));
+
+        let other = dir.path().join("ruby-3.4.2.tgz");
+        fs::write(&other, "tarball contents").unwrap();
+        assert!(matches!(
+            verify(&other, &provenance),
+            Err(ProvenanceError::NoSubject { .. })
+        ));
+    }
+}
@@ -0,0 +1,529 @@
+//! The pipeline shared by the release check commands
+//!
+//! Checking an engine for unbuilt releases is the same every time: list the upstream releases
+//! worth having, work out each one's artifact name, `HEAD` that artifact for every entry of the
+//! [`build_matrix`] on S3, and report the versions with missing binaries. [`ReleaseEngine`]
+//! captures what differs between Ruby and JRuby, [`check`] runs the pipeline, and [`Report`]
+//! renders one or more engines' results as Markdown or JSON.
+
+use crate::{BaseImage, S3_BASE_URL, build_matrix, s3_url_exists, summary};
+use libherokubuildpack::inventory::artifact::Arch;
+use reqwest::Url;
+use serde::{Serialize, Serializer};
+use std::fmt::{self, Display, Write};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// A type erased error that can cross task boundaries
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Something went wrong while checking, along with which step it happened in
+///
+/// Serializes as its message.
+#[derive(Debug, thiserror::Error)]
+#[error("{context}: {source}")]
+pub struct CheckError {
+    pub context: String,
+    #[source]
+    pub source: BoxError,
+}
+
+impl CheckError {
+    pub fn new(context: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl Serialize for CheckError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The releases an engine wants binaries for
+///
+/// Listing can partially succeed (a failed page, an unparsable entry), so the versions that
+/// were found are kept alongside the errors. `notes` carry anything else worth telling the
+/// reader, such as releases held back by a settle window.
+#[derive(Debug)]
+pub struct Releases<V> {
+    pub versions: Vec<V>,
+    pub notes: Vec<String>,
+    pub errors: Vec<CheckError>,
+}
+
+impl<V> Default for Releases<V> {
+    fn default() -> Self {
+        Self {
+            versions: Vec::new(),
+            notes: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+/// What differs between checking one engine's releases and another's
+pub trait ReleaseEngine: Send + Sync + 'static {
+    type Version: Clone + Display + Send + Sync + 'static;
+
+    /// Shown in reports, e.g. `Ruby` or `JRuby`
+    fn name(&self) -> &'static str;
+
+    /// The releases that should have binaries on S3, already narrowed down to the versions
+    /// worth checking (minimum version, settle window, ...)
+    fn releases(&self) -> impl Future<Output = Releases<Self::Version>> + Send;
+
+    /// File name of the artifact for `version`, e.g. `ruby-3.4.1.tgz`
+    ///
+    /// It's the same on every base image and architecture. Engines whose artifact name depends
+    /// on more than the version (such as JRuby's Ruby stdlib version) resolve that here.
+    fn artifact_name(
+        &self,
+        version: &Self::Version,
+    ) -> impl Future<Output = Result<String, BoxError>> + Send;
+}
+
+/// Where the artifact for a base image and architecture lives on S3
+pub fn artifact_url(base_image: &BaseImage, arch: &Arch, artifact_name: &str) -> Url {
+    let mut url = Url::parse(S3_BASE_URL).expect("internal Url is parsable");
+    url.path_segments_mut()
+        .expect("valid base URL")
+        .push(base_image.name())
+        .push(&arch.to_string())
+        .push(artifact_name);
+    url
+}
+
+/// One base image and architecture of the build matrix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Target {
+    pub base_image: String,
+    pub arch: String,
+    pub url: Url,
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base_image, self.arch)
+    }
+}
+
+/// Whether each build matrix entry of a version is on S3
+///
+/// Entries that couldn't be checked are `errored`, the reason is in the engine's errors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionStatus {
+    pub version: String,
+    pub artifact: String,
+    pub present: Vec<Target>,
+    pub missing: Vec<Target>,
+    pub errored: Vec<Target>,
+}
+
+impl VersionStatus {
+    fn cell(&self, target: &str) -> String {
+        let find = |targets: &[Target]| {
+            targets
+                .iter()
+                .find(|t| t.to_string() == target)
+                .map(|t| t.url.clone())
+        };
+        if let Some(url) = find(&self.present) {
+            summary::link_cell("✅ present", &url)
+        } else if let Some(url) = find(&self.missing) {
+            summary::link_cell("❌ missing", &url)
+        } else if let Some(url) = find(&self.errored) {
+            summary::link_cell("⚠️ error", &url)
+        } else {
+            "-".to_string()
+        }
+    }
+}
+
+/// The outcome of checking one engine
+#[derive(Debug, Serialize)]
+pub struct EngineReport {
+    pub engine: String,
+    /// Every checked version, in the order the engine listed them
+    pub versions: Vec<VersionStatus>,
+    pub notes: Vec<String>,
+    pub errors: Vec<CheckError>,
+}
+
+impl EngineReport {
+    /// Versions missing at least one binary
+    pub fn to_build(&self) -> Vec<String> {
+        self.versions
+            .iter()
+            .filter(|status| !status.missing.is_empty())
+            .map(|status| status.version.clone())
+            .collect()
+    }
+}
+
+/// Run the release check for `engine` against S3
+pub async fn check<E: ReleaseEngine>(engine: Arc<E>) -> EngineReport {
+    check_with(engine, |url| async move {
+        let client = crate::http::client().map_err(BoxError::from)?;
+        s3_url_exists(client, url).await.map_err(BoxError::from)
+    })
+    .await
+}
+
+/// Like [`check`] with an injected `probe` reporting whether a URL exists
+pub async fn check_with<E, P, Fut>(engine: Arc<E>, probe: P) -> EngineReport
+where
+    E: ReleaseEngine,
+    P: Fn(Url) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<bool, BoxError>> + Send + 'static,
+{
+    let Releases {
+        versions,
+        notes,
+        mut errors,
+    } = engine.releases().await;
+
+    let mut set = JoinSet::new();
+    for (index, version) in versions.into_iter().enumerate() {
+        let engine = engine.clone();
+        let probe = probe.clone();
+        set.spawn(async move {
+            let status = check_version(engine.as_ref(), &version, probe).await;
+            (index, status)
+        });
+    }
+
+    let mut statuses = Vec::new();
+    while let Some(result) = set.join_next().await {
+        match result {
+            Ok((index, Ok((status, probe_errors)))) => {
+                statuses.push((index, status));
+                errors.extend(probe_errors);
+            }
+            Ok((_, Err(error))) => errors.push(error),
+            Err(join_error) => errors.push(CheckError::new("task panicked", join_error)),
+        }
+    }
+    statuses.sort_by_key(|(index, _)| *index);
+
+    EngineReport {
+        engine: engine.name().to_string(),
+        versions: statuses.into_iter().map(|(_, status)| status).collect(),
+        notes,
+        errors,
+    }
+}
+
+async fn check_version<E, P, Fut>(
+    engine: &E,
+    version: &E::Version,
+    probe: P,
+) -> Result<(VersionStatus, Vec<CheckError>), CheckError>
+where
+    E: ReleaseEngine,
+    P: Fn(Url) -> Fut,
+    Fut: Future<Output = Result<bool, BoxError>> + Send + 'static,
+{
+    let artifact = engine
+        .artifact_name(version)
+        .await
+        .map_err(|error| CheckError::new(format!("resolving artifact for {version}"), error))?;
+
+    let mut set = JoinSet::new();
+    for (base_image, arch) in build_matrix() {
+        let target = Target {
+            url: artifact_url(&base_image, &arch, &artifact),
+            base_image: base_image.name().to_string(),
+            arch: arch.to_string(),
+        };
+        let exists = probe(target.url.clone());
+        set.spawn(async move { (exists.await, target) });
+    }
+
+    let mut present = Vec::new();
+    let mut missing = Vec::new();
+    let mut errored = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(result) = set.join_next().await {
+        let (exists, target) = result
+            .map_err(|error| CheckError::new(format!("task panicked checking {version}"), error))?;
+        match exists {
+            Ok(true) => present.push(target),
+            Ok(false) => missing.push(target),
+            Err(error) => {
+                errors.push(CheckError::new(
+                    format!("checking S3 for {version} at {}", target.url),
+                    error,
+                ));
+                errored.push(target);
+            }
+        }
+    }
+    present.sort_by_key(ToString::to_string);
+    missing.sort_by_key(ToString::to_string);
+    errored.sort_by_key(ToString::to_string);
+
+    Ok((
+        VersionStatus {
+            version: version.to_string(),
+            artifact,
+            present,
+            missing,
+            errored,
+        },
+        errors,
+    ))
+}
+
+/// Results of checking one or more engines
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub engines: Vec<EngineReport>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.engines.iter().any(|engine| !engine.errors.is_empty())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report serializes to JSON")
+    }
+
+    /// A table per engine of every checked version against every base image and architecture,
+    /// linking each cell to its S3 object, followed by notes and errors
+    pub fn to_markdown(&self) -> String {
+        let targets = build_matrix()
+            .iter()
+            .map(|(base_image, arch)| format!("{}/{arch}", base_image.name()))
+            .collect::<Vec<_>>();
+
+        let mut out = String::new();
+        for engine in &self.engines {
+            let _ = writeln!(out, "## {}\n", engine.engine);
+
+            if engine.versions.is_empty() {
+                let _ = writeln!(out, "No versions checked\n");
+            } else {
+                let mut table = summary::Table::new(
+                    ["Version", "Artifact"]
+                        .into_iter()
+                        .map(String::from)
+                        .chain(targets.iter().cloned()),
+                );
+                for status in &engine.versions {
+                    table.row(
+                        [status.version.clone(), format!("`{}`", status.artifact)]
+                            .into_iter()
+                            .chain(targets.iter().map(|target| status.cell(target))),
+                    );
+                }
+                let _ = writeln!(out, "{table}");
+            }
+
+            for (heading, items) in [
+                (
+                    "Notes",
+                    engine.notes.iter().map(ToString::to_string).collect(),
+                ),
+                (
+                    "Errors",
+                    engine.errors.iter().map(ToString::to_string).collect(),
+                ),
+            ] as [(&str, Vec<String>); 2]
+            {
+                if !items.is_empty() {
+                    let _ = writeln!(out, "{heading}:\n");
+                    for item in items {
+                        let _ = writeln!(out, "- {}", item.replace('\n', "\n  "));
+                    }
+                    let _ = writeln!(out);
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeEngine;
+
+    impl ReleaseEngine for FakeEngine {
+        type Version = String;
+
+        fn name(&self) -> &'static str {
+            "Fake"
+        }
+
+        async fn releases(&self) -> Releases<String> {
+            Releases {
+                versions: vec![
+                    "2.0.0".to_string(),
+                    "1.0.0".to_string(),
+                    "0.1.0".to_string(),
+                ],
+                notes: vec!["3.0.0: skipping, published recently".to_string()],
+                errors: Vec::new(),
+            }
+        }
+
+        async fn artifact_name(&self, version: &String) -> Result<String, BoxError> {
+            if version == "0.1.0" {
+                Err("no stdlib version".into())
+            } else {
+                Ok(format!("fake-{version}.tgz"))
+            }
+        }
+    }
+
+    async fn fake_report() -> EngineReport {
+        // 2.0.0 is missing everywhere on heroku-24, 1.0.0 is fully present
+        check_with(Arc::new(FakeEngine), |url: Url| async move {
+            Ok(!(url.path().contains("fake-2.0.0") && url.path().contains("heroku-24")))
+        })
+        .await
+    }
+
+    #[test]
+    fn test_artifact_url() {
+        let urls = build_matrix()
+            .iter()
+            .map(|(base_image, arch)| {
+                artifact_url(base_image, arch, "ruby-3.1.4-jruby-9.4.7.0.tgz").to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(urls.len(), 5);
+
+        for expected in [
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-22/amd64/ruby-3.1.4-jruby-9.4.7.0.tgz",
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-24/amd64/ruby-3.1.4-jruby-9.4.7.0.tgz",
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-24/arm64/ruby-3.1.4-jruby-9.4.7.0.tgz",
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-26/amd64/ruby-3.1.4-jruby-9.4.7.0.tgz",
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-26/arm64/ruby-3.1.4-jruby-9.4.7.0.tgz",
+        ] {
+            assert!(
+                urls.iter().any(|url| url == expected),
+                "expected `{expected}` in collection but it was not found:\n{urls:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn check_reports_missing_targets_in_listed_order() {
+        let report = fake_report().await;
+
+        assert_eq!(report.engine, "Fake");
+        assert_eq!(
+            report
+                .versions
+                .iter()
+                .map(|status| status.version.as_str())
+                .collect::<Vec<_>>(),
+            vec!["2.0.0", "1.0.0"]
+        );
+        assert_eq!(
+            report.versions[0]
+                .missing
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["heroku-24/amd64", "heroku-24/arm64"]
+        );
+        assert!(report.versions[1].missing.is_empty());
+        assert_eq!(report.to_build(), vec!["2.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn check_keeps_going_after_a_version_fails() {
+        let report = fake_report().await;
+
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            report.errors[0].to_string(),
+            "resolving artifact for 0.1.0: no stdlib version"
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_errors_are_reported_per_version() {
+        let report = check_with(Arc::new(FakeEngine), |url: Url| async move {
+            if url.path().contains("fake-1.0.0") {
+                Err("connection reset".into())
+            } else {
+                Ok(true)
+            }
+        })
+        .await;
+
+        assert_eq!(report.versions.len(), 2);
+        assert_eq!(report.versions[1].version, "1.0.0");
+        assert!(report.versions[1].present.is_empty());
+        assert!(report.versions[1].missing.is_empty());
+        assert_eq!(report.versions[1].errored.len(), build_matrix().len());
+        assert_eq!(report.to_build(), Vec::<String>::new());
+        // One per build matrix entry of 1.0.0, plus 0.1.0's artifact name
+        assert_eq!(report.errors.len(), build_matrix().len() + 1);
+        assert!(
+            report
+                .errors
+                .iter()
+                .any(|error| error.context.starts_with("checking S3 for 1.0.0")),
+            "got: {:?}",
+            report.errors
+        );
+    }
+
+    #[tokio::test]
+    async fn report_renders_markdown_and_json() {
+        let report = Report {
+            engines: vec![fake_report().await],
+        };
+
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("## Fake\n"), "got:\n{markdown}");
+        assert!(
+            markdown.contains("| Version | Artifact | heroku-22/amd64 | heroku-24/amd64 | heroku-24/arm64 | heroku-26/amd64 | heroku-26/arm64 |"),
+            "got:\n{markdown}"
+        );
+        let base = "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com";
+        assert!(
+            markdown.contains(&format!(
+                "| 2.0.0 | `fake-2.0.0.tgz` | [✅ present]({base}/heroku-22/amd64/fake-2.0.0.tgz) | [❌ missing]({base}/heroku-24/amd64/fake-2.0.0.tgz) |"
+            )),
+            "got:\n{markdown}"
+        );
+        assert!(
+            markdown.contains(&format!(
+                "| 1.0.0 | `fake-1.0.0.tgz` | [✅ present]({base}/heroku-22/amd64/fake-1.0.0.tgz) |"
+            )),
+            "got:\n{markdown}"
+        );
+        assert!(
+            markdown.contains("Notes:\n\n- 3.0.0: skipping, published recently\n"),
+            "got:\n{markdown}"
+        );
+        assert!(
+            markdown.contains("Errors:\n\n- resolving artifact for 0.1.0: no stdlib version\n"),
+            "got:\n{markdown}"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["engines"][0]["engine"], "Fake");
+        assert_eq!(
+            json["engines"][0]["versions"][0]["missing"][0]["url"],
+            "https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-24/amd64/fake-2.0.0.tgz"
+        );
+        assert_eq!(
+            json["engines"][0]["errors"][0],
+            "resolving artifact for 0.1.0: no stdlib version"
+        );
+        assert!(report.has_errors());
+    }
+}
@@ -0,0 +1,161 @@
+//! Checks that an extracted Ruby doesn't depend on the directory it was built in
+//!
+//! `make_ruby.sh` installs into [`BUILD_PREFIX`](crate::audit::BUILD_PREFIX) with
+//! `--enable-load-relative`, so Ruby finds its libraries relative to its own binary. The build
+//! directory can still leak into `rbconfig.rb`, pkg-config `.pc` files and the shebangs of `bin/`
+//! scripts and gem executables, which [`prefix_leaks`] looks for.
+
+use fs_err as fs;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+/// A line mentioning the build prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixLeak {
+    /// Relative to the extracted root
+    pub path: PathBuf,
+    /// 1 based
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for PrefixLeak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.text)
+    }
+}
+
+/// Files where every line is checked
+const CONFIG_PATTERNS: [&str; 2] = ["lib/ruby/*/*/rbconfig.rb", "lib/pkgconfig/*.pc"];
+
+/// Scripts where only the shebang is checked, and the ones [`crate::update_shebangs_in_dir`]
+/// rewrites
+const SCRIPT_PATTERNS: [&str; 3] = [
+    "bin/*",
+    "lib/ruby/gems/*/gems/*/exe/*",
+    "lib/ruby/gems/*/gems/*/bin/*",
+];
+
+/// Find references to `prefix` in the Ruby extracted to `root`, see the module docs
+pub fn prefix_leaks(root: &Path, prefix: &str) -> std::io::Result<Vec<PrefixLeak>> {
+    let mut leaks = Vec::new();
+    for (patterns, shebang_only) in [(&CONFIG_PATTERNS[..], false), (&SCRIPT_PATTERNS[..], true)] {
+        for path in matching_files(root, patterns)? {
+            let contents = fs::read(&path)?;
+            let contents = String::from_utf8_lossy(&contents);
+            let lines = if shebang_only {
+                contents
+                    .lines()
+                    .take(1)
+                    .filter(|line| line.starts_with("#!"))
+                    .collect::<Vec<_>>()
+            } else {
+                contents.lines().collect()
+            };
+            leaks.extend(
+                lines
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, text)| text.contains(prefix) && !is_expected(text))
+                    .map(|(index, text)| PrefixLeak {
+                        path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                        line: index + 1,
+                        text: text.trim().to_string(),
+                    }),
+            );
+        }
+    }
+    Ok(leaks)
+}
+
+/// `bin/` scripts and gem executables below `root`
+pub fn scripts(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    matching_files(root, &SCRIPT_PATTERNS)
+}
+
+/// True for a path, relative to the root of a Ruby, that [`scripts`] would return
+pub fn is_script(path: &Path) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::default()
+    };
+    SCRIPT_PATTERNS.iter().any(|pattern| {
+        glob::Pattern::new(pattern)
+            .expect("internal glob is valid")
+            .matches_path_with(path, options)
+    })
+}
+
+/// Lines of `rbconfig.rb` that mention the prefix without Ruby using it: the fallback for when
+/// `TOPDIR` can't be worked out from the file's own location, and the recorded `./configure`
+/// arguments
+fn is_expected(line: &str) -> bool {
+    line.contains("TOPDIR ||") || line.contains("CONFIG[\"configure_args\"]")
+}
+
+fn matching_files(root: &Path, patterns: &[&str]) -> std::io::Result<Vec<PathBuf>> {
+    let root = glob::Pattern::escape(&root.to_string_lossy());
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        for path in glob::glob(&format!("{root}/{pattern}")).expect("internal glob is valid") {
+            let path = path.map_err(glob::GlobError::into_error)?;
+            // Symlinks are skipped so rewriting a script can't write through one
+            if fs::symlink_metadata(&path)?.is_file() {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_leaked_prefixes() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(
+            "lib/ruby/3.4.0/x86_64-linux/rbconfig.rb",
+            indoc::indoc! {r#"
+                TOPDIR = File.dirname(__FILE__).chomp!("/lib/ruby/3.4.0/x86_64-linux")
+                CONFIG["prefix"] = (TOPDIR || DESTDIR + "/tmp/compiled")
+                CONFIG["configure_args"] = " '--prefix' '/tmp/compiled'"
+                CONFIG["rubyhdrdir"] = "/tmp/compiled/include"
+            "#},
+        );
+        write("lib/pkgconfig/ruby-3.4.pc", "prefix=${pcfiledir}/../..\n");
+        write("bin/gem", "#!/bin/sh\n# exec \"/tmp/compiled/bin/ruby\"\n");
+        write("bin/rake", "#!/tmp/compiled/bin/ruby\n");
+        write(
+            "lib/ruby/gems/3.4.0/gems/rbs-3.8.0/exe/rbs",
+            "#!/tmp/compiled/bin/ruby -w\n",
+        );
+
+        let leaks = prefix_leaks(dir.path(), "/tmp/compiled").unwrap();
+        assert_eq!(
+            leaks.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "lib/ruby/3.4.0/x86_64-linux/rbconfig.rb:4: CONFIG[\"rubyhdrdir\"] = \"/tmp/compiled/include\"",
+                "bin/rake:1: #!/tmp/compiled/bin/ruby",
+                "lib/ruby/gems/3.4.0/gems/rbs-3.8.0/exe/rbs:1: #!/tmp/compiled/bin/ruby -w",
+            ]
+        );
+    }
+
+    #[test]
+    fn scripts_are_bin_and_gem_executables() {
+        assert!(is_script(Path::new("bin/rake")));
+        assert!(is_script(Path::new(
+            "lib/ruby/gems/shared/gems/rake-13.2.1/exe/rake"
+        )));
+        assert!(!is_script(Path::new("bin/nested/rake")));
+        assert!(!is_script(Path::new("lib/ruby/3.4.0/un.rb")));
+    }
+}
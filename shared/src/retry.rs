@@ -0,0 +1,367 @@
+//! Retrying fallible async operations
+//!
+//! Not every failure is worth retrying. A 404, a parse error, or a checksum mismatch will fail the
+//! same way no matter how many times it is attempted, while a 503, a timeout, or a reset
+//! connection often succeeds a moment later. Errors report which kind they are via [`Classify`]
+//! and [`RetryPolicy::retry`] only backs off and tries again for [`ErrorClass::Transient`] ones.
+//!
+//! ```
+//! use shared::retry::{Classify, ErrorClass, RetryPolicy};
+//!
+//! #[derive(Debug)]
+//! struct NotFound;
+//!
+//! impl Classify for NotFound {
+//!     fn classify(&self) -> ErrorClass {
+//!         ErrorClass::Permanent
+//!     }
+//! }
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let mut attempts = 0;
+//! let result: Result<(), NotFound> = RetryPolicy::default()
+//!     .retry(|| {
+//!         attempts += 1;
+//!         async { Err(NotFound) }
+//!     })
+//!     .await;
+//!
+//! assert!(result.is_err());
+//! assert_eq!(attempts, 1);
+//! # });
+//! ```
+
+use crate::{MAX_RETRY_ATTEMPTS, RETRY_DELAY};
+use reqwest::StatusCode;
+use std::time::{Duration, Instant};
+
+/// Whether an error is worth retrying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Will fail the same way every time (4xx, parse errors, checksum mismatches, ...)
+    Permanent,
+    /// May succeed if attempted again. When the server said how long to wait (via `Retry-After`)
+    /// that duration is used instead of the computed backoff.
+    Transient { retry_after: Option<Duration> },
+}
+
+impl ErrorClass {
+    pub const TRANSIENT: ErrorClass = ErrorClass::Transient { retry_after: None };
+
+    /// Classify an HTTP status. Server errors, `408 Request Timeout` and `429 Too Many Requests`
+    /// are transient, everything else is permanent.
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>) -> ErrorClass {
+        if status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+        {
+            ErrorClass::Transient { retry_after }
+        } else {
+            ErrorClass::Permanent
+        }
+    }
+}
+
+/// Implemented by errors that can be retried by [`RetryPolicy::retry`]
+pub trait Classify {
+    fn classify(&self) -> ErrorClass;
+}
+
+impl Classify for reqwest::Error {
+    fn classify(&self) -> ErrorClass {
+        if let Some(status) = self.status() {
+            ErrorClass::from_status(status, None)
+        } else if self.is_timeout() || self.is_connect() || self.is_request() || self.is_body() {
+            ErrorClass::TRANSIENT
+        } else {
+            ErrorClass::Permanent
+        }
+    }
+}
+
+impl Classify for std::io::Error {
+    fn classify(&self) -> ErrorClass {
+        use std::io::ErrorKind;
+        match self.kind() {
+            ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::UnexpectedEof => ErrorClass::TRANSIENT,
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// How many times, and how far apart, to attempt an operation
+///
+/// The delay before attempt `n + 1` is `base_delay * 2^(n - 1)`, capped at `max_delay`. With
+/// `jitter` enabled a random amount of up to half of that delay is subtracted so that many
+/// concurrent tasks failing at once don't retry in lockstep. A server provided `Retry-After`
+/// replaces the computed delay, but is still capped at `max_delay` so a misbehaving server
+/// can't stall a run.
+///
+/// When a `deadline` is set, no attempt is started if waiting for it would exceed the deadline
+/// (measured from the first attempt), and the last error is returned instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u8,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: MAX_RETRY_ATTEMPTS,
+            base_delay: RETRY_DELAY,
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay to wait after the given (1 based) failed attempt
+    pub fn delay_for(&self, attempt: u8, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exponent = u32::from(attempt.saturating_sub(1)).min(31);
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        if self.jitter {
+            backoff.saturating_sub(backoff.mul_f64(random_fraction() / 2.0))
+        } else {
+            backoff
+        }
+    }
+
+    /// Run `f` until it succeeds, returns a permanent error, or the policy gives up
+    pub async fn retry<T, E, F, Fut>(&self, mut f: F) -> Result<T, E>
+    where
+        E: Classify,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match f().await {
+                Ok(val) => return Ok(val),
+                Err(error) => error,
+            };
+
+            let ErrorClass::Transient { retry_after } = error.classify() else {
+                return Err(error);
+            };
+            if attempts >= self.max_attempts {
+                return Err(error);
+            }
+
+            let delay = self.delay_for(attempts, retry_after);
+            if let Some(deadline) = self.deadline
+                && start.elapsed() + delay > deadline
+            {
+                return Err(error);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Uniformly distributed value in `[0, 1)`
+///
+/// Jitter only needs to spread retries apart, not be unpredictable, so the randomly keyed std
+/// hasher is used rather than pulling in a dependency.
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq)]
+    enum TestError {
+        Transient,
+        RetryAfter(Duration),
+        Permanent,
+    }
+
+    impl Classify for TestError {
+        fn classify(&self) -> ErrorClass {
+            match self {
+                TestError::Transient => ErrorClass::TRANSIENT,
+                TestError::RetryAfter(duration) => ErrorClass::Transient {
+                    retry_after: Some(*duration),
+                },
+                TestError::Permanent => ErrorClass::Permanent,
+            }
+        }
+    }
+
+    fn no_jitter(base_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay,
+            max_delay: Duration::from_secs(10),
+            jitter: false,
+            deadline: None,
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_and_is_capped() {
+        let policy = no_jitter(Duration::from_secs(1));
+        assert_eq!(policy.delay_for(1, None), Duration::from_secs(1));
+        assert_eq!(policy.delay_for(2, None), Duration::from_secs(2));
+        assert_eq!(policy.delay_for(3, None), Duration::from_secs(4));
+        assert_eq!(policy.delay_for(4, None), Duration::from_secs(8));
+        assert_eq!(policy.delay_for(5, None), Duration::from_secs(10));
+        assert_eq!(policy.delay_for(u8::MAX, None), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_of_backoff() {
+        let policy = RetryPolicy {
+            jitter: true,
+            ..no_jitter(Duration::from_secs(1))
+        };
+        for _ in 0..100 {
+            let delay = policy.delay_for(3, None);
+            assert!(delay <= Duration::from_secs(4), "got {delay:?}");
+            assert!(delay >= Duration::from_secs(2), "got {delay:?}");
+        }
+    }
+
+    #[test]
+    fn retry_after_replaces_backoff() {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(60),
+            ..no_jitter(Duration::from_secs(1))
+        };
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(42))),
+            Duration::from_secs(42)
+        );
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_max_delay() {
+        let policy = no_jitter(Duration::from_secs(1));
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(365 * 24 * 60 * 60))),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            RetryPolicy::default().delay_for(1, Some(Duration::MAX)),
+            RetryPolicy::default().max_delay
+        );
+    }
+
+    #[test]
+    fn status_classification() {
+        assert_eq!(
+            ErrorClass::from_status(StatusCode::NOT_FOUND, None),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            ErrorClass::from_status(StatusCode::FORBIDDEN, None),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            ErrorClass::from_status(StatusCode::SERVICE_UNAVAILABLE, None),
+            ErrorClass::TRANSIENT
+        );
+        assert_eq!(
+            ErrorClass::from_status(StatusCode::TOO_MANY_REQUESTS, Some(Duration::from_secs(3))),
+            ErrorClass::Transient {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let attempts = AtomicUsize::new(0);
+        let result: Result<(), TestError> = no_jitter(Duration::ZERO)
+            .retry(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(TestError::Permanent) }
+            })
+            .await;
+
+        assert_eq!(result, Err(TestError::Permanent));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_max_attempts() {
+        let attempts = AtomicUsize::new(0);
+        let result: Result<(), TestError> = no_jitter(Duration::ZERO)
+            .retry(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(TestError::Transient) }
+            })
+            .await;
+
+        assert_eq!(result, Err(TestError::Transient));
+        assert_eq!(attempts.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_respected() {
+        let attempts = AtomicUsize::new(0);
+        let start = Instant::now();
+        let result: Result<&str, TestError> = no_jitter(Duration::ZERO)
+            .retry(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if attempt == 1 {
+                        Err(TestError::RetryAfter(Duration::from_millis(20)))
+                    } else {
+                        Ok("ok")
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok("ok"));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn deadline_stops_retries() {
+        let attempts = AtomicUsize::new(0);
+        let policy = RetryPolicy {
+            deadline: Some(Duration::from_millis(10)),
+            ..no_jitter(Duration::from_secs(1))
+        };
+        let result: Result<(), TestError> = policy
+            .retry(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(TestError::Transient) }
+            })
+            .await;
+
+        assert_eq!(result, Err(TestError::Transient));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,661 @@
+//! CycloneDX software bill of materials for built tarballs
+//!
+//! [`inspect_tarball`] reads a Ruby or JRuby tarball without extracting it and finds the gems
+//! it ships (from their gemspecs under `lib/ruby/gems/<abi>/specifications`, or
+//! `lib/ruby/gems/shared` for JRuby), the RubyGems version, and the shared libraries its ELF
+//! files link against that the tarball doesn't provide itself (i.e. the ones the base image
+//! must supply). [`Inventory::to_cyclonedx`] turns that into a [CycloneDX 1.5] document.
+//!
+//! [CycloneDX 1.5]: https://cyclonedx.org/docs/1.5/json/
+
+use crate::{Compression, sha256_from_path};
+use fs_err as fs;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SbomError {
+    #[error("Cannot read tarball {path}: {source}")]
+    CannotRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Cannot compute sha256 of {path}: {source}")]
+    CannotDigest {
+        path: PathBuf,
+        #[source]
+        source: Box<crate::Error>,
+    },
+
+    #[error(
+        "Cannot tell what {name} contains, expected `ruby-<version>.tgz` or \
+        `ruby-<stdlib version>-jruby-<version>.tgz`, optionally with a `-<sha>` suffix (or `.tar.zst`, \
+        `.tar.xz`)"
+    )]
+    UnknownArtifact { name: String },
+}
+
+/// Which runtime a tarball holds, from its file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    Ruby {
+        version: String,
+    },
+    JRuby {
+        version: String,
+        ruby_stdlib_version: String,
+    },
+}
+
+impl Artifact {
+    /// Parse a name like `ruby-3.4.1.tgz` or `ruby-3.1.4-jruby-9.4.7.0.tar.zst`
+    ///
+    /// The SHA copies uploaded next to them (`ruby-3.4.1-abcdef0.tgz`) parse the same. Versions
+    /// never contain a `-`, so any other suffix is rejected.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (stem, _) = Compression::from_file_name(name)?;
+        let stem = stem.strip_prefix("ruby-")?;
+        let stem = match stem.rsplit_once('-') {
+            Some((rest, sha_seven))
+                if sha_seven.len() == 7
+                    && sha_seven
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)) =>
+            {
+                rest
+            }
+            _ => stem,
+        };
+        let is_version = |version: &str| !version.is_empty() && !version.contains('-');
+        match stem.split_once("-jruby-") {
+            Some((ruby_stdlib_version, version))
+                if is_version(ruby_stdlib_version) && is_version(version) =>
+            {
+                Some(Artifact::JRuby {
+                    version: version.to_string(),
+                    ruby_stdlib_version: ruby_stdlib_version.to_string(),
+                })
+            }
+            None if is_version(stem) => Some(Artifact::Ruby {
+                version: stem.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Where a gem came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GemKind {
+    /// Part of the standard library, can't be uninstalled
+    Default,
+    /// Installed alongside Ruby, like `minitest` or `rake`
+    Bundled,
+}
+
+impl GemKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GemKind::Default => "default",
+            GemKind::Bundled => "bundled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Gem {
+    pub name: String,
+    pub version: String,
+    pub kind: GemKind,
+}
+
+/// A shared library that isn't in the tarball, and the files that need it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedLibrary {
+    pub name: String,
+    pub needed_by: Vec<String>,
+}
+
+/// What's inside a tarball
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inventory {
+    pub artifact: Artifact,
+    pub file_name: String,
+    pub sha256: String,
+    pub rubygems_version: Option<String>,
+    pub gems: Vec<Gem>,
+    pub libraries: Vec<LinkedLibrary>,
+}
+
+/// Inspect the tarball at `path`, see the module docs
+pub fn inspect_tarball(path: &Path) -> Result<Inventory, SbomError> {
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let (artifact, compression) = Artifact::from_file_name(&file_name)
+        .zip(Compression::from_file_name(&file_name).map(|(_, compression)| compression))
+        .ok_or_else(|| SbomError::UnknownArtifact {
+            name: file_name.clone(),
+        })?;
+    let sha256 = sha256_from_path(path).map_err(|source| SbomError::CannotDigest {
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    })?;
+    let file = fs::File::open(path).map_err(|source| SbomError::CannotRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let contents = compression
+        .decoder(file)
+        .and_then(read_contents)
+        .map_err(|source| SbomError::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+    Ok(Inventory {
+        artifact,
+        file_name,
+        sha256,
+        rubygems_version: contents.rubygems_version,
+        gems: contents.gems.into_iter().collect(),
+        libraries: contents
+            .needed
+            .into_iter()
+            .filter(|(name, _)| !contents.provided.contains(name))
+            .map(|(name, needed_by)| LinkedLibrary {
+                name,
+                needed_by: needed_by.into_iter().collect(),
+            })
+            .collect(),
+    })
+}
+
+#[derive(Debug, Default)]
+struct Contents {
+    rubygems_version: Option<String>,
+    gems: BTreeSet<Gem>,
+    /// File names of everything in the tarball, a needed library in here is provided
+    provided: BTreeSet<String>,
+    /// Needed library name to the paths that need it
+    needed: BTreeMap<String, BTreeSet<String>>,
+}
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+
+/// Walk an uncompressed tar stream
+fn read_contents(tar: impl Read) -> std::io::Result<Contents> {
+    let mut contents = Contents::default();
+    let mut archive = tar::Archive::new(tar);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = normalize(&entry.path()?);
+        let Some(name) = path.rsplit('/').next().map(String::from) else {
+            continue;
+        };
+        contents.provided.insert(name.clone());
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        (&mut entry).take(4).read_to_end(&mut bytes)?;
+        if bytes.as_slice() == ELF_MAGIC {
+            entry.read_to_end(&mut bytes)?;
+            if let Ok(goblin::Object::Elf(elf)) = goblin::Object::parse(&bytes) {
+                for library in elf.libraries {
+                    contents
+                        .needed
+                        .entry(library.to_string())
+                        .or_default()
+                        .insert(path.clone());
+                }
+            }
+        } else if let Some(kind) = gemspec_kind(&path) {
+            entry.read_to_end(&mut bytes)?;
+            let gemspec = String::from_utf8_lossy(&bytes);
+            let gem = gemspec_field(&gemspec, "name")
+                .zip(gemspec_field(&gemspec, "version"))
+                .or_else(|| split_gemspec_name(&name));
+            if let Some((name, version)) = gem {
+                contents.gems.insert(Gem {
+                    name,
+                    version,
+                    kind,
+                });
+            }
+        } else if is_rubygems_rb(&path) {
+            entry.read_to_end(&mut bytes)?;
+            contents.rubygems_version = rubygems_version(&String::from_utf8_lossy(&bytes));
+        }
+    }
+    Ok(contents)
+}
+
+/// `./lib/ruby` and `lib/ruby` are the same place
+fn normalize(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `lib/ruby/gems/<abi>/specifications/[default/]<name>.gemspec`
+fn gemspec_kind(path: &str) -> Option<GemKind> {
+    let parts = path.split('/').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["lib", "ruby", "gems", _, "specifications", "default", name]
+            if name.ends_with(".gemspec") =>
+        {
+            Some(GemKind::Default)
+        }
+        ["lib", "ruby", "gems", _, "specifications", name] if name.ends_with(".gemspec") => {
+            Some(GemKind::Bundled)
+        }
+        _ => None,
+    }
+}
+
+/// `lib/ruby/<abi>/rubygems.rb`, or `lib/ruby/stdlib/rubygems.rb` on JRuby
+fn is_rubygems_rb(path: &str) -> bool {
+    matches!(
+        path.split('/').collect::<Vec<_>>().as_slice(),
+        ["lib", "ruby", _, "rubygems.rb"]
+    )
+}
+
+/// The string assigned to `s.<field>` in a generated gemspec, e.g. `s.version = "1.2.3".freeze`
+fn gemspec_field(gemspec: &str, field: &str) -> Option<String> {
+    let prefix = format!("s.{field} = \"");
+    gemspec.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(&prefix)?;
+        value.split_once('"').map(|(value, _)| value.to_string())
+    })
+}
+
+/// Fall back to the file name, `<name>-<version>[-<platform>].gemspec`
+fn split_gemspec_name(file_name: &str) -> Option<(String, String)> {
+    let stem = file_name.strip_suffix(".gemspec")?;
+    let parts = stem.split('-').collect::<Vec<_>>();
+    let index = parts
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find(|(_, part)| part.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|(index, _)| index)?;
+    Some((parts[..index].join("-"), parts[index].to_string()))
+}
+
+/// `VERSION = "3.6.2"` from `rubygems.rb`
+fn rubygems_version(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("VERSION = \"")?;
+        value.split_once('"').map(|(value, _)| value.to_string())
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BomHash {
+    pub alg: &'static str,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BomComponent {
+    #[serde(rename = "type")]
+    pub component_type: &'static str,
+    #[serde(rename = "bom-ref")]
+    pub bom_ref: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hashes: Vec<BomHash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BomMetadata {
+    pub component: BomComponent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    #[serde(rename = "ref")]
+    pub dependency_ref: String,
+    pub depends_on: Vec<String>,
+}
+
+/// A CycloneDX document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bom {
+    pub bom_format: &'static str,
+    pub spec_version: &'static str,
+    pub serial_number: String,
+    pub version: u32,
+    pub metadata: BomMetadata,
+    pub components: Vec<BomComponent>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl Inventory {
+    /// The SBOM for this tarball
+    ///
+    /// It has no timestamp and its serial number derives from the tarball's checksum, so the
+    /// same tarball always produces the same document.
+    pub fn to_cyclonedx(&self) -> Bom {
+        let (name, version, mut properties) = match &self.artifact {
+            Artifact::Ruby { version } => ("ruby", version.clone(), Vec::new()),
+            Artifact::JRuby {
+                version,
+                ruby_stdlib_version,
+            } => (
+                "jruby",
+                version.clone(),
+                vec![Property {
+                    name: "heroku:ruby-stdlib-version".to_string(),
+                    value: ruby_stdlib_version.clone(),
+                }],
+            ),
+        };
+        properties.push(Property {
+            name: "heroku:file-name".to_string(),
+            value: self.file_name.clone(),
+        });
+        let root = BomComponent {
+            component_type: "application",
+            bom_ref: format!("pkg:generic/{name}@{version}"),
+            name: name.to_string(),
+            purl: Some(format!("pkg:generic/{name}@{version}")),
+            version: Some(version),
+            hashes: vec![BomHash {
+                alg: "SHA-256",
+                content: self.sha256.clone(),
+            }],
+            properties,
+        };
+
+        let mut components = Vec::new();
+        if let Some(version) = &self.rubygems_version {
+            components.push(BomComponent {
+                component_type: "library",
+                bom_ref: format!("pkg:generic/rubygems@{version}"),
+                name: "rubygems".to_string(),
+                version: Some(version.clone()),
+                purl: Some(format!("pkg:generic/rubygems@{version}")),
+                hashes: Vec::new(),
+                properties: Vec::new(),
+            });
+        }
+        components.extend(self.gems.iter().map(|gem| {
+            let purl = format!("pkg:gem/{}@{}", gem.name, gem.version);
+            BomComponent {
+                component_type: "library",
+                bom_ref: purl.clone(),
+                name: gem.name.clone(),
+                version: Some(gem.version.clone()),
+                purl: Some(purl),
+                hashes: Vec::new(),
+                properties: vec![Property {
+                    name: "heroku:gem-kind".to_string(),
+                    value: gem.kind.as_str().to_string(),
+                }],
+            }
+        }));
+        components.extend(self.libraries.iter().map(|library| {
+            BomComponent {
+                component_type: "library",
+                bom_ref: format!("system-library:{}", library.name),
+                name: library.name.clone(),
+                version: None,
+                purl: None,
+                hashes: Vec::new(),
+                properties: library
+                    .needed_by
+                    .iter()
+                    .map(|path| Property {
+                        name: "heroku:needed-by".to_string(),
+                        value: path.clone(),
+                    })
+                    .collect(),
+            }
+        }));
+
+        Bom {
+            bom_format: "CycloneDX",
+            spec_version: "1.5",
+            serial_number: serial_number(&self.sha256),
+            version: 1,
+            dependencies: vec![Dependency {
+                dependency_ref: root.bom_ref.clone(),
+                depends_on: components
+                    .iter()
+                    .map(|component| component.bom_ref.clone())
+                    .collect(),
+            }],
+            metadata: BomMetadata { component: root },
+            components,
+        }
+    }
+}
+
+/// A UUID URN made from the first 16 bytes of a sha256 hex digest, marked as a version 8
+/// (custom) UUID
+fn serial_number(sha256: &str) -> String {
+    let mut hex = format!("{sha256:0<32}")
+        .chars()
+        .take(32)
+        .collect::<Vec<_>>();
+    hex[12] = '8';
+    hex[16] = match hex[16].to_digit(16).unwrap_or(0) & 0x3 {
+        0 => '8',
+        1 => '9',
+        2 => 'a',
+        _ => 'b',
+    };
+    let hex = hex.into_iter().collect::<String>();
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, contents: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+
+    #[test]
+    fn artifact_from_file_name() {
+        assert_eq!(
+            Artifact::from_file_name("ruby-3.4.1.tgz"),
+            Some(Artifact::Ruby {
+                version: "3.4.1".to_string()
+            })
+        );
+        assert_eq!(
+            Artifact::from_file_name("ruby-3.1.4-jruby-9.4.7.0.tgz"),
+            Some(Artifact::JRuby {
+                version: "9.4.7.0".to_string(),
+                ruby_stdlib_version: "3.1.4".to_string()
+            })
+        );
+        assert_eq!(
+            Artifact::from_file_name("ruby-3.4.1.tar.xz"),
+            Some(Artifact::Ruby {
+                version: "3.4.1".to_string()
+            })
+        );
+        assert_eq!(
+            Artifact::from_file_name("ruby-3.4.1-abcdef0.tgz"),
+            Some(Artifact::Ruby {
+                version: "3.4.1".to_string()
+            })
+        );
+        assert_eq!(
+            Artifact::from_file_name("ruby-3.1.4-jruby-9.4.7.0-abcdef0.tgz"),
+            Some(Artifact::JRuby {
+                version: "9.4.7.0".to_string(),
+                ruby_stdlib_version: "3.1.4".to_string()
+            })
+        );
+        assert_eq!(Artifact::from_file_name("ruby-3.4.1-latest.tgz"), None);
+        assert_eq!(Artifact::from_file_name("ruby-3.4.1-abcdefg.tgz"), None);
+        assert_eq!(Artifact::from_file_name("python-3.12.tgz"), None);
+        assert_eq!(Artifact::from_file_name("ruby-3.4.1.zip"), None);
+    }
+
+    #[test]
+    fn gemspec_names() {
+        assert_eq!(
+            gemspec_field("  s.name = \"net-http\".freeze\n", "name"),
+            Some("net-http".to_string())
+        );
+        assert_eq!(
+            split_gemspec_name("net-http-0.6.0.gemspec"),
+            Some(("net-http".to_string(), "0.6.0".to_string()))
+        );
+        assert_eq!(
+            split_gemspec_name("ffi-1.16.3-java.gemspec"),
+            Some(("ffi".to_string(), "1.16.3".to_string()))
+        );
+    }
+
+    #[test]
+    fn reads_gems_rubygems_and_provided_files() {
+        let mut builder = tar::Builder::new(Vec::new());
+        append(
+            &mut builder,
+            "./lib/ruby/gems/3.4.0/specifications/default/json-2.9.1.gemspec",
+            b"Gem::Specification.new do |s|\n  s.name = \"json\".freeze\n  s.version = \"2.9.1\".freeze\nend\n",
+        );
+        append(
+            &mut builder,
+            "./lib/ruby/gems/3.4.0/specifications/minitest-5.25.4.gemspec",
+            b"# no fields we understand\n",
+        );
+        append(
+            &mut builder,
+            "./lib/ruby/3.4.0/rubygems.rb",
+            b"module Gem\n  VERSION = \"3.6.2\"\nend\n",
+        );
+        append(&mut builder, "./lib/libruby.so.3.4", b"not really ELF");
+        let contents = read_contents(builder.into_inner().unwrap().as_slice()).unwrap();
+
+        assert_eq!(contents.rubygems_version.as_deref(), Some("3.6.2"));
+        assert_eq!(
+            contents.gems.into_iter().collect::<Vec<_>>(),
+            vec![
+                Gem {
+                    name: "json".to_string(),
+                    version: "2.9.1".to_string(),
+                    kind: GemKind::Default
+                },
+                Gem {
+                    name: "minitest".to_string(),
+                    version: "5.25.4".to_string(),
+                    kind: GemKind::Bundled
+                },
+            ]
+        );
+        assert!(contents.provided.contains("libruby.so.3.4"));
+    }
+
+    #[test]
+    fn reads_elf_needed_libraries() {
+        // Any ELF binary with dynamic dependencies will do, the test binary is one
+        let exe = std::env::current_exe().unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        append(&mut builder, "bin/ruby", &fs::read(&exe).unwrap());
+        let contents = read_contents(builder.into_inner().unwrap().as_slice()).unwrap();
+
+        assert!(
+            contents
+                .needed
+                .get("libc.so.6")
+                .is_some_and(|needed_by| needed_by.contains("bin/ruby")),
+            "got: {:?}",
+            contents.needed
+        );
+    }
+
+    #[test]
+    fn cyclonedx_document() {
+        let inventory = Inventory {
+            artifact: Artifact::Ruby {
+                version: "3.4.1".to_string(),
+            },
+            file_name: "ruby-3.4.1.tgz".to_string(),
+            sha256: "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef".to_string(),
+            rubygems_version: Some("3.6.2".to_string()),
+            gems: vec![Gem {
+                name: "json".to_string(),
+                version: "2.9.1".to_string(),
+                kind: GemKind::Default,
+            }],
+            libraries: vec![LinkedLibrary {
+                name: "libyaml-0.so.2".to_string(),
+                needed_by: vec!["lib/ruby/3.4.0/x86_64-linux/psych.so".to_string()],
+            }],
+        };
+        let json = serde_json::to_value(inventory.to_cyclonedx()).unwrap();
+
+        assert_eq!(json["bomFormat"], "CycloneDX");
+        assert_eq!(
+            json["serialNumber"],
+            "urn:uuid:01234567-89ab-8def-8123-456789abcdef"
+        );
+        assert_eq!(
+            json["metadata"]["component"]["purl"],
+            "pkg:generic/ruby@3.4.1"
+        );
+        assert_eq!(
+            json["components"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|component| component["name"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["rubygems", "json", "libyaml-0.so.2"]
+        );
+        assert_eq!(json["components"][1]["purl"], "pkg:gem/json@2.9.1");
+        assert_eq!(
+            json["dependencies"][0]["dependsOn"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Holding back releases until they have been public for a while
+//!
+//! A release is announced before every mirror, CDN, and package repository has its artifacts,
+//! so a build dispatched the moment a release appears can fail to download it. Release checks
+//! compare the release's publish time against a settle window and only schedule builds for
+//! releases older than that.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// How much longer a release published at `published_at` has to wait before it has been public
+/// for `window`, or `None` when it already has
+pub fn settle_remaining(
+    published_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    window: TimeDelta,
+) -> Option<TimeDelta> {
+    let remaining = published_at + window - now;
+    (remaining > TimeDelta::zero()).then_some(remaining)
+}
+
+/// Format a remaining settle time for humans, rounded up to the minute (e.g. `1h 5m`)
+pub fn format_remaining(remaining: TimeDelta) -> String {
+    let minutes = (remaining.num_seconds().max(0) + 59) / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, 0) => format!("{hours}h"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settled_once_window_has_passed() {
+        let published = DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let window = TimeDelta::hours(1);
+
+        assert_eq!(
+            settle_remaining(published, published + TimeDelta::minutes(20), window),
+            Some(TimeDelta::minutes(40))
+        );
+        assert_eq!(
+            settle_remaining(published, published + TimeDelta::hours(1), window),
+            None
+        );
+        assert_eq!(
+            settle_remaining(published, published + TimeDelta::days(3), window),
+            None
+        );
+        assert_eq!(
+            settle_remaining(published, published, TimeDelta::zero()),
+            None
+        );
+    }
+
+    #[test]
+    fn remaining_is_rounded_up_to_minutes() {
+        assert_eq!(format_remaining(TimeDelta::seconds(1)), "1m");
+        assert_eq!(format_remaining(TimeDelta::minutes(40)), "40m");
+        assert_eq!(format_remaining(TimeDelta::hours(2)), "2h");
+        assert_eq!(
+            format_remaining(TimeDelta::minutes(65) + TimeDelta::seconds(1)),
+            "1h 6m"
+        );
+    }
+}
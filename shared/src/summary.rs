@@ -0,0 +1,197 @@
+//! Markdown reports for GitHub step summaries
+//!
+//! Anything a step appends to `$GITHUB_STEP_SUMMARY` is rendered as Markdown on the workflow
+//! run's summary page. Commands accept an optional `--summary-markdown <PATH>` and append a
+//! report there with [`append_markdown`], so several commands (or matrix jobs) can share a file.
+
+use crate::{BaseImage, BuildStatus, S3_BASE_URL, release_check};
+use fs_err as fs;
+use libherokubuildpack::inventory::artifact::Arch;
+use reqwest::Url;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::path::Path;
+
+/// Appends `markdown` to the given file path, creating it if needed
+///
+/// Returns `Ok(())` when `path` is `None` (no-op), so callers can pass
+/// the optional `--summary-markdown` argument directly.
+pub fn append_markdown(path: Option<&Path>, markdown: &str) -> std::io::Result<()> {
+    let Some(path) = path else { return Ok(()) };
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{markdown}"))
+}
+
+/// A table cell with `label` linking to `url`
+pub fn link_cell(label: &str, url: &Url) -> String {
+    format!("[{label}]({url})")
+}
+
+/// A GitHub flavored Markdown table
+///
+/// Pipes in cells are escaped so they don't start a new column.
+#[derive(Debug, Clone)]
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            header: header.into_iter().collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, cells: impl IntoIterator<Item = String>) -> &mut Self {
+        self.rows.push(cells.into_iter().collect());
+        self
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |f: &mut fmt::Formatter<'_>, cells: &[String]| {
+            let cells = cells
+                .iter()
+                .map(|cell| cell.replace('|', "\\|"))
+                .collect::<Vec<_>>();
+            writeln!(f, "| {} |", cells.join(" | "))
+        };
+        line(f, &self.header)?;
+        writeln!(f, "|{}", "---|".repeat(self.header.len()))?;
+        for row in &self.rows {
+            line(f, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of one build job, for the step summary
+#[derive(Debug, Clone)]
+pub struct BuildSummary {
+    /// e.g. `Ruby` or `JRuby`
+    pub engine: &'static str,
+    pub version: String,
+    pub base_image: BaseImage,
+    /// `None` for architecture independent builds, which live at the base image's root
+    pub arch: Option<Arch>,
+    /// `None` when the build failed
+    pub status: Option<BuildStatus>,
+    /// File name of the artifact when known, linked to its S3 location
+    pub artifact: Option<String>,
+}
+
+impl BuildSummary {
+    pub fn to_markdown(&self) -> String {
+        let status = match self.status {
+            Some(BuildStatus::Success) => "✅ built",
+            Some(BuildStatus::Skipped) => "⏭️ skipped, already exists",
+            None => "⚠️ error",
+        };
+        let artifact = self.artifact.as_deref().map_or("-".to_string(), |name| {
+            let url = match &self.arch {
+                Some(arch) => release_check::artifact_url(&self.base_image, arch, name),
+                None => {
+                    let mut url = Url::parse(S3_BASE_URL).expect("internal Url is parsable");
+                    url.path_segments_mut()
+                        .expect("valid base URL")
+                        .push(self.base_image.name())
+                        .push(name);
+                    url
+                }
+            };
+            link_cell(name, &url)
+        });
+
+        let mut table =
+            Table::new(["Version", "Base image", "Arch", "Status", "Artifact"].map(String::from));
+        table.row([
+            self.version.clone(),
+            self.base_image.to_string(),
+            self.arch.map_or("any".to_string(), |arch| arch.to_string()),
+            status.to_string(),
+            artifact,
+        ]);
+        format!("## {} build\n\n{table}", self.engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_escapes_pipes() {
+        let mut table = Table::new(["Name", "Value"].map(String::from));
+        table.row(["a|b".to_string(), "c".to_string()]);
+
+        assert_eq!(
+            table.to_string(),
+            indoc::indoc! {r"
+                | Name | Value |
+                |---|---|
+                | a\|b | c |
+            "}
+        );
+    }
+
+    #[test]
+    fn build_summary_links_artifact() {
+        let summary = BuildSummary {
+            engine: "Ruby",
+            version: "3.4.1".to_string(),
+            base_image: BaseImage::new("heroku-24").unwrap(),
+            arch: Some(Arch::Arm64),
+            status: Some(BuildStatus::Success),
+            artifact: Some("ruby-3.4.1.tgz".to_string()),
+        };
+
+        assert_eq!(
+            summary.to_markdown(),
+            indoc::indoc! {"
+                ## Ruby build
+
+                | Version | Base image | Arch | Status | Artifact |
+                |---|---|---|---|---|
+                | 3.4.1 | heroku-24 | arm64 | ✅ built | [ruby-3.4.1.tgz](https://heroku-buildpack-ruby.s3.dualstack.us-east-1.amazonaws.com/heroku-24/arm64/ruby-3.4.1.tgz) |
+            "}
+        );
+    }
+
+    #[test]
+    fn build_summary_without_arch_or_artifact() {
+        let summary = BuildSummary {
+            engine: "JRuby",
+            version: "9.4.7.0".to_string(),
+            base_image: BaseImage::new("heroku-24").unwrap(),
+            arch: None,
+            status: None,
+            artifact: None,
+        };
+
+        assert!(
+            summary
+                .to_markdown()
+                .contains("| 9.4.7.0 | heroku-24 | any | ⚠️ error | - |"),
+            "got:\n{}",
+            summary.to_markdown()
+        );
+    }
+
+    #[test]
+    fn append_markdown_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("summary.md");
+
+        append_markdown(Some(&path), "# one").unwrap();
+        append_markdown(Some(&path), "# two").unwrap();
+        append_markdown(None, "# ignored").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# one\n# two\n");
+    }
+}
@@ -0,0 +1,6 @@
+# Arbitrary precision decimal arithmetic
+require "bigdecimal"
+
+sum = BigDecimal("0.1") + BigDecimal("0.2")
+abort "Expected 0.3, got #{sum}" unless sum == BigDecimal("0.3")
+puts "bigdecimal #{BigDecimal::VERSION}"
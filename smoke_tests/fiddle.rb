@@ -0,0 +1,8 @@
+# Calling into libc through libffi
+require "fiddle"
+
+libc = Fiddle.dlopen(nil)
+strlen = Fiddle::Function.new(libc["strlen"], [Fiddle::TYPE_VOIDP], Fiddle::TYPE_SIZE_T)
+length = strlen.call("ruby")
+abort "Expected strlen to return 4, got #{length}" unless length == 4
+puts "fiddle #{Fiddle::VERSION}"
@@ -0,0 +1,44 @@
+# TLS handshake with a local server using a throwaway self-signed certificate
+require "openssl"
+require "socket"
+
+key = OpenSSL::PKey::RSA.new(2048)
+cert = OpenSSL::X509::Certificate.new
+cert.version = 2
+cert.serial = 1
+cert.subject = cert.issuer = OpenSSL::X509::Name.parse("/CN=localhost")
+cert.public_key = key.public_key
+cert.not_before = Time.now - 60
+cert.not_after = Time.now + 3600
+extensions = OpenSSL::X509::ExtensionFactory.new(cert, cert)
+cert.add_extension(extensions.create_extension("basicConstraints", "CA:TRUE", true))
+cert.add_extension(extensions.create_extension("keyUsage", "keyCertSign,digitalSignature", true))
+cert.add_extension(extensions.create_extension("subjectAltName", "DNS:localhost"))
+cert.sign(key, OpenSSL::Digest.new("SHA256"))
+
+server_context = OpenSSL::SSL::SSLContext.new
+server_context.cert = cert
+server_context.key = key
+tcp_server = TCPServer.new("127.0.0.1", 0)
+server = OpenSSL::SSL::SSLServer.new(tcp_server, server_context)
+thread = Thread.new do
+  connection = server.accept
+  connection.puts(connection.gets)
+  connection.close
+end
+
+client_context = OpenSSL::SSL::SSLContext.new
+client_context.cert_store = OpenSSL::X509::Store.new.tap { |store| store.add_cert(cert) }
+client_context.verify_mode = OpenSSL::SSL::VERIFY_PEER
+client = OpenSSL::SSL::SSLSocket.new(TCPSocket.new("127.0.0.1", tcp_server.addr[1]), client_context)
+client.sync_close = true
+client.hostname = "localhost"
+client.connect
+protocol = client.ssl_version
+client.puts("ping")
+reply = client.gets
+client.close
+thread.join
+
+abort "Expected ping back, got #{reply.inspect}" unless reply == "ping\n"
+puts "#{OpenSSL::OPENSSL_LIBRARY_VERSION} #{protocol}"
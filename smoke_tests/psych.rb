@@ -0,0 +1,6 @@
+# YAML parsing and dumping through libyaml
+require "yaml"
+
+document = {"ruby" => [1, 2.5, "three"], "nested" => {"ok" => true}}
+abort "YAML didn't round trip" unless YAML.load(YAML.dump(document)) == document
+puts "psych #{Psych::VERSION}, libyaml #{Psych::LIBYAML_VERSION}"
@@ -0,0 +1,10 @@
+# Line editing, Ruby 3.3 replaced the readline extension (libreadline) with reline
+if Gem::Version.new(RUBY_VERSION) >= Gem::Version.new("3.3")
+  require "reline"
+  abort "Reline can't read lines" unless Reline.respond_to?(:readline)
+  puts "reline #{Reline::VERSION}"
+else
+  require "readline"
+  abort "Readline can't read lines" unless Readline.respond_to?(:readline)
+  puts "readline #{Readline::VERSION}"
+end
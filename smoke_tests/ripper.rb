@@ -0,0 +1,6 @@
+# Parsing Ruby source
+require "ripper"
+
+sexp = Ripper.sexp("1 + 1")
+abort "Ripper couldn't parse `1 + 1`" unless sexp&.first == :program
+puts "ripper ok"
@@ -0,0 +1,9 @@
+# YJIT is compiled in (`--enable-yjit`) and can be turned on
+#
+# `RubyVM::YJIT.enable` was added in Ruby 3.3, older versions exit 77 to be reported as skipped.
+exit 77 if Gem::Version.new(RUBY_VERSION) < Gem::Version.new("3.3")
+
+abort "RubyVM::YJIT is not defined, Ruby was built without YJIT" unless defined?(RubyVM::YJIT)
+RubyVM::YJIT.enable
+abort "RubyVM::YJIT.enabled? is false after RubyVM::YJIT.enable" unless RubyVM::YJIT.enabled?
+puts "yjit enabled"
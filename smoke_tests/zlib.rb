@@ -0,0 +1,11 @@
+# Deflate and gzip round trips
+require "zlib"
+require "stringio"
+
+input = "ruby " * 1000
+abort "Inflate didn't round trip" unless Zlib::Inflate.inflate(Zlib::Deflate.deflate(input)) == input
+
+io = StringIO.new
+Zlib::GzipWriter.wrap(io) { |gz| gz.write(input) }
+abort "Gzip didn't round trip" unless Zlib.gunzip(io.string) == input
+puts "zlib #{Zlib.zlib_version}"